@@ -0,0 +1,1042 @@
+//! Merge the entries of multiple RSS channels into one channel.
+
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use rss::{Channel, Item};
+
+
+/// Field identifying a RSS entry
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupKey {
+    Guid,
+    Link,
+    Title,
+}
+
+/// Where new entries are put relative to the entries of the main channel
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Insert {
+    /// Before the main entries
+    Top,
+    /// After the main entries
+    Bottom,
+}
+
+/// Channel whose title, link, description and other fields are used for the merged channel
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Primary {
+    /// The main channel
+    First,
+    /// The last extra channel
+    Last,
+}
+
+/// Channel whose `<image>` is used for the merged channel
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFrom {
+    /// The main channel
+    Main,
+    /// The last extra channel
+    Last,
+    /// No image at all
+    None,
+}
+
+/// Where the `<ttl>` of the merged channel comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ttl {
+    /// The lowest ttl of all channels, so that readers poll often enough for each of them
+    Auto,
+    /// The ttl of the main channel
+    Keep,
+    /// This number of minutes
+    Minutes(u32),
+}
+
+/// How to combine the channels
+#[derive(Clone, Debug)]
+pub struct CombineOptions {
+    /// Maximum number of entries; use 0 for unlimited entries
+    pub max_entries: usize,
+    /// Keep this percentage of the entries instead of `max_entries`, rounded to the nearest number
+    /// of entries; 0 keeps none and 100 keeps all
+    pub keep_percent: Option<u8>,
+    /// Maximum number of new entries taken from each extra channel; use 0 for unlimited entries
+    pub limit_per_source: usize,
+    /// When limiting to `max_entries`, take the entries round-robin from each channel instead of
+    /// simply keeping the first ones. The main channel counts as one channel.
+    pub fair_truncate: bool,
+    /// Field used to detect duplicate entries; None uses the GUID, falling back to the link
+    pub dedup_key: Option<DedupKey>,
+    /// Normalize the dedup key using [`normalize_guid`] before comparing
+    pub normalize_guid: bool,
+    /// Lowercase the dedup key before comparing. This can wrongly merge entries of feeds where
+    /// the case of GUIDs is significant
+    pub dedup_ignore_case: bool,
+    /// Also treat entries with the same title and link as duplicates, even if their GUIDs differ
+    pub dedup_by_content: bool,
+    /// Also treat entries with the same enclosure URL as duplicates, even if their GUIDs differ
+    pub dedup_by_enclosure: bool,
+    /// Also treat entries with the same [`media_url`] as duplicates, even if their GUIDs differ
+    pub dedup_by_media_url: bool,
+    /// Within each channel, only keep the newest of the entries with exactly the same title. This
+    /// is done before looking for duplicates across channels.
+    pub title_dedup: bool,
+    /// Merge new entries lacking a dedup key instead of ignoring them; these are only recognized
+    /// as duplicates by their title and link
+    pub keep_guidless: bool,
+    /// Sort the entries newest first; otherwise new entries are put where `insert` says
+    pub sort: bool,
+    /// Put new entries before or after the main entries. When sorting this only matters for
+    /// entries with the same date. Without sorting and with `Bottom`, `max_entries` drops the
+    /// entries at the top, as the newest are at the bottom.
+    pub insert: Insert,
+    /// Put the entries in a random order using this seed, instead of sorting them. Combined with
+    /// `max_entries` this keeps a random selection of the entries.
+    pub shuffle: Option<u64>,
+    /// Reverse the order of the entries, e.g. oldest first when sorting. This is done after
+    /// limiting to `max_entries`, so the same entries are kept.
+    pub reverse: bool,
+    /// Channel to take the channel fields from; this does not affect the order of the entries
+    pub primary: Primary,
+    /// Channel to take the image from, regardless of `primary`
+    pub image_from: ImageFrom,
+    /// Set the ttl of the merged channel, instead of using the one of wherever the channel fields
+    /// come from
+    pub ttl: Option<Ttl>,
+    /// Drop entries published before this date
+    pub since: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// The current time for `max_age`, instead of the actual time
+    pub now: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// Drop entries older than this, relative to now
+    pub max_age: Option<chrono::Duration>,
+    /// Also drop entries without a (valid) date when using `since` or `max_age`
+    pub drop_undated: bool,
+    /// Extension element with the date an entry expires, as prefix and name; expired entries are
+    /// dropped, entries without it are kept
+    pub expiry_element: Option<(String, String)>,
+    /// Never drop entries of the main channel and ignore `max_entries`, only new entries are
+    /// filtered
+    pub append_only: bool,
+    /// Only merge new entries having one of these categories; empty merges all entries
+    pub include_categories: Vec<String>,
+    /// Never merge new entries having one of these categories
+    pub exclude_categories: Vec<String>,
+    /// Compare categories ignoring case
+    pub category_case_insensitive: bool,
+    /// Only merge new entries with a title matching this
+    pub title_match: Option<regex::Regex>,
+    /// Never merge new entries with a title matching this
+    pub title_reject: Option<regex::Regex>,
+    /// Put this before the titles of new entries; %name is replaced by the title of their channel
+    pub prefix_title: Option<String>,
+    /// Also apply `prefix_title` to the entries of the main channel
+    pub prefix_all: bool,
+    /// GUIDs (or whichever field is used as dedup key) merged during earlier runs; entries with
+    /// these are never merged again
+    pub seen_guids: HashSet<String>,
+    /// Turn the HTML descriptions of all entries into plain text using [`strip_html`]
+    pub strip_html: bool,
+    /// Shorten descriptions to at most this many characters using [`truncate_text`]. Descriptions
+    /// that look like HTML are only shortened together with `strip_html`, as cutting them could
+    /// leave unclosed tags. 0 means no limit, like for `max_entries`.
+    pub max_description_chars: Option<usize>,
+    /// Resolve relative links of entries against the link of their channel
+    pub absolute_links: bool,
+    /// Resolve relative links against this URL instead of the link of the channel
+    pub base_url: Option<url::Url>,
+    /// Set this as author of the entries lacking both an author and a `<dc:creator>`
+    pub default_author: Option<String>,
+    /// For duplicate entries, set the `<slash:comments>` count of the kept entry to the highest
+    /// count of all its copies
+    pub merge_comments: bool,
+    /// For duplicate entries, replace the kept entry by a copy with a newer date. Entries are
+    /// never replaced when either lacks a (valid) date.
+    pub merge_update: bool,
+    /// Set the `<source>` of new entries to the channel they came from, replacing any existing one
+    pub add_source: bool,
+}
+
+impl Default for CombineOptions {
+    fn default() -> Self {
+        CombineOptions {
+            max_entries: 0,
+            keep_percent: None,
+            limit_per_source: 0,
+            fair_truncate: false,
+            dedup_key: None,
+            normalize_guid: false,
+            dedup_ignore_case: false,
+            dedup_by_content: false,
+            dedup_by_enclosure: false,
+            dedup_by_media_url: false,
+            title_dedup: false,
+            keep_guidless: false,
+            sort: true,
+            insert: Insert::Top,
+            shuffle: None,
+            reverse: false,
+            primary: Primary::Last,
+            image_from: ImageFrom::Main,
+            ttl: None,
+            since: None,
+            now: None,
+            max_age: None,
+            drop_undated: false,
+            expiry_element: None,
+            append_only: false,
+            include_categories: Vec::new(),
+            exclude_categories: Vec::new(),
+            category_case_insensitive: false,
+            title_match: None,
+            title_reject: None,
+            prefix_title: None,
+            prefix_all: false,
+            seen_guids: HashSet::new(),
+            strip_html: false,
+            max_description_chars: None,
+            absolute_links: false,
+            base_url: None,
+            default_author: None,
+            merge_comments: false,
+            merge_update: false,
+            add_source: false,
+        }
+    }
+}
+
+impl CombineOptions {
+    /// The form of a dedup key used for comparing, applying `normalize_guid` and
+    /// `dedup_ignore_case`
+    pub fn compare_key(&self, key: &str) -> String {
+        let key = if self.normalize_guid { normalize_guid(key) } else { key.to_string() };
+        if self.dedup_ignore_case { key.to_lowercase() } else { key }
+    }
+}
+
+/// What made an entry a duplicate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DuplicateOf {
+    /// The dedup key
+    Key,
+    /// The title and link, see `dedup_by_content`
+    Content,
+    /// The enclosure URL, see `dedup_by_enclosure`
+    Enclosure,
+    /// The title within the same channel, see `title_dedup`
+    Title,
+    /// The `<media:content>` URL, see `dedup_by_media_url`
+    Media,
+}
+
+/// An entry skipped as duplicate of an earlier entry
+#[derive(Clone, Debug)]
+pub struct Duplicate {
+    /// Dedup key of the skipped entry, or its title if it has none
+    pub key: String,
+    /// Channel of the skipped entry, 1 being the first extra channel
+    pub source: usize,
+    /// Channel of the earlier entry, 0 being the main channel
+    pub earlier_source: usize,
+    pub of: DuplicateOf,
+}
+
+/// Numbers collected during the merge
+#[derive(Default, Debug)]
+pub struct Stats {
+    /// Entries in the main channel
+    pub main_items: usize,
+    /// Entries in each extra channel
+    pub file_items: Vec<usize>,
+    /// GUIDs occurring more than once within the main channel
+    pub main_repeated_guids: Vec<String>,
+    /// GUIDs occurring more than once within each extra channel
+    pub file_repeated_guids: Vec<Vec<String>>,
+    /// Entries merged into the main channel
+    pub new_items: usize,
+    /// GUIDs (or whichever field is used as dedup key) of the merged entries
+    pub new_guids: Vec<String>,
+    /// Entries skipped because their GUID was already known
+    pub duplicates: usize,
+    /// Details of each of the `duplicates`
+    pub duplicate_entries: Vec<Duplicate>,
+    /// Entries skipped because their GUID is in `seen_guids`
+    pub seen_before: usize,
+    /// New entries skipped because their extra channel reached `limit_per_source`
+    pub dropped_per_source: usize,
+    /// New entries skipped because of their categories
+    pub filtered_category: usize,
+    /// New entries skipped because of their title
+    pub filtered_title: usize,
+    /// Entries replaced by a newer copy, see `merge_update`
+    pub updated_items: usize,
+    /// Entries dropped because they are older than `since` or `max_age`
+    pub dropped_old: usize,
+    /// Entries dropped because they expired according to `expiry_element`
+    pub dropped_expired: usize,
+    /// Entries of the main channel dropped because another one has the same title, see
+    /// `title_dedup`
+    pub dropped_title: usize,
+    /// Entries dropped because of the maximum number of entries
+    pub dropped_max_entries: usize,
+    /// Entries ignored because they have no GUID
+    pub missing_guids: usize,
+    /// Positions within the combined channel of the entries merged from the extra channels
+    pub new_positions: Vec<usize>,
+}
+
+impl Stats {
+    fn add_duplicate(&mut self, key: &str, source: usize, earlier_source: usize, of: DuplicateOf) {
+        self.duplicates += 1;
+        self.duplicate_entries.push(Duplicate { key: key.to_string(), source, earlier_source, of });
+    }
+
+    /// Whether the combined channel differs from the main channel, meaning entries were added or
+    /// removed
+    pub fn changed(&self) -> bool {
+        self.new_items > 0 || self.updated_items > 0 || self.dropped_old > 0 || self.dropped_expired > 0
+            || self.dropped_title > 0 || self.dropped_max_entries > 0
+    }
+}
+
+/// Determine the value used to recognize duplicate RSS entries
+///
+/// Without an explicit key the GUID is used, falling back to the link for RSS files without GUIDs
+pub fn dedup_key(item: &Item, key: Option<DedupKey>) -> Option<&str> {
+    match key {
+        Some(DedupKey::Guid) => item.guid().map(|guid| guid.value()),
+        Some(DedupKey::Link) => item.link(),
+        Some(DedupKey::Title) => item.title(),
+        None => item.guid().map(|guid| guid.value()).or_else(|| item.link()),
+    }
+}
+
+/// Canonicalize a GUID so that trivially different variants are seen as duplicates
+///
+/// Trims whitespace and a trailing /. For URLs the scheme and host are lowercased, any other
+/// GUID is otherwise passed through unchanged.
+pub fn normalize_guid(guid: &str) -> String {
+    let guid = guid.trim();
+    let guid = guid.strip_suffix('/').unwrap_or(guid);
+
+    let scheme_end = match guid.find("://") {
+        Some(scheme_end) if scheme_end > 0 && guid[..scheme_end].chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.') => scheme_end,
+        _ => return guid.to_string(),
+    };
+
+    let host_start = scheme_end + "://".len();
+    let host_end = guid[host_start..].find(['/', '?', '#'])
+        .map_or(guid.len(), |host_len| host_start + host_len);
+    format!("{}{}", guid[..host_end].to_ascii_lowercase(), &guid[host_end..])
+}
+
+/// Drop the entries having the same title as a newer entry, keeping the first one if they have
+/// the same date; returns the titles of the dropped entries
+///
+/// Entries without a title are all kept.
+fn dedup_titles(items: &mut Vec<Item>) -> Vec<String> {
+    let mut newest: HashMap<&str, (usize, Option<chrono::DateTime<chrono::FixedOffset>>)> = HashMap::new();
+    for (index, item) in items.iter().enumerate() {
+        let Some(title) = item.title() else { continue };
+        let date = item.pub_date().and_then(parse_item_date);
+        let entry = newest.entry(title).or_insert((index, date));
+        if date > entry.1 {
+            *entry = (index, date);
+        }
+    }
+    let keep: HashSet<usize> = newest.into_values().map(|(index, _)| index).collect();
+
+    let mut dropped = Vec::new();
+    let mut index = 0;
+    items.retain(|item| {
+        let kept = item.title().is_none() || keep.contains(&index);
+        if !kept {
+            dropped.extend(item.title().map(str::to_string));
+        }
+        index += 1;
+        kept
+    });
+    dropped
+}
+
+/// Hash the title and link of an entry, ignoring case and surrounding whitespace
+///
+/// Returns None for entries without either
+fn content_hash(item: &Item) -> Option<u64> {
+    if item.title().is_none() && item.link().is_none() {
+        return None;
+    }
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    item.title().map(|title| title.trim().to_lowercase()).hash(&mut hasher);
+    item.link().map(str::trim).hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// Whether any category of the entry is in `names`
+fn has_category(item: &Item, names: &[String], case_insensitive: bool) -> bool {
+    item.categories().iter().any(|category| {
+        let category = category.name().trim();
+        names.iter().any(|name| if case_insensitive {
+            name.to_lowercase() == category.to_lowercase()
+        } else {
+            name == category
+        })
+    })
+}
+
+/// The author of an entry, preferring `<dc:creator>` as that is usually a name rather than an
+/// email address
+pub fn item_author(item: &Item) -> Option<&str> {
+    item.dublin_core_ext()
+        .and_then(|dc| dc.creators().first())
+        .map(String::as_str)
+        .or(item.author())
+}
+
+/// Namespace of Media RSS
+const MEDIA_NAMESPACE: &str = "http://search.yahoo.com/mrss/";
+
+/// The prefix a channel uses for Media RSS, usually media
+fn media_prefix(channel: &Channel) -> &str {
+    channel.namespaces().iter()
+        .find(|(_, uri)| uri.as_str() == MEDIA_NAMESPACE)
+        .map_or("media", |(prefix, _)| prefix.as_str())
+}
+
+/// The URL of the first `<media:content>` of an entry, also within a `<media:group>` as used by
+/// YouTube; `prefix` is the one used for the Media RSS namespace
+pub fn media_url<'a>(item: &'a Item, prefix: &str) -> Option<&'a str> {
+    let media = item.extensions().get(prefix)?;
+    let content = media.get("content").and_then(|contents| contents.first())
+        .or_else(|| media.get("group")?.first()?.children().get("content")?.first())?;
+    content.attrs().get("url").map(String::as_str)
+}
+
+/// Namespace of the `<slash:comments>` extension
+const SLASH_NAMESPACE: &str = "http://purl.org/rss/1.0/modules/slash/";
+
+/// The number of comments of an entry according to `<slash:comments>`
+pub fn comment_count(item: &Item) -> Option<u64> {
+    item.extensions().get("slash")?.get("comments")?.first()?.value()?.trim().parse().ok()
+}
+
+/// Set the `<slash:comments>` count of an entry, replacing any existing one
+fn set_comment_count(item: &mut Item, count: u64) {
+    let mut extension = rss::extension::Extension::default();
+    extension.set_name("slash:comments");
+    extension.set_value(count.to_string());
+    item.extensions.entry("slash".to_string()).or_default().insert("comments".to_string(), vec![extension]);
+}
+
+/// The URL of the channel itself according to `<atom:link rel="self">`
+pub fn self_link(channel: &Channel) -> Option<&str> {
+    channel.extensions().get("atom")?.get("link")?.iter()
+        .find(|link| link.attrs().get("rel").map(String::as_str) == Some("self"))?
+        .attrs().get("href").map(String::as_str)
+}
+
+/// A `<source>` pointing to the channel, using its self link or else its website
+fn channel_source(channel: &Channel) -> Option<rss::Source> {
+    let url = self_link(channel).unwrap_or(channel.link());
+    if url.is_empty() {
+        return None;
+    }
+    Some(rss::Source { url: url.to_string(), title: Some(channel.title().to_string()).filter(|title| !title.is_empty()) })
+}
+
+/// Put `prefix` before the title of the entry, replacing %name by `name`
+///
+/// Titles already starting with the prefix are left alone, so merging into a previously merged
+/// channel does not add it twice. No escaping is needed, that is done when writing the channel.
+fn prefix_title(item: &mut Item, prefix: &str, name: &str) {
+    let prefix = prefix.replace("%name", name.trim());
+    if let Some(title) = item.title().filter(|title| !title.starts_with(&prefix)) {
+        let title = format!("{}{}", prefix, title);
+        item.set_title(title);
+    }
+}
+
+/// Resolve the relative link and enclosure URL of the entry against `base`
+///
+/// Absolute URLs and anything which cannot be resolved are left as is
+pub fn absolutize_links(item: &mut Item, base: &url::Url) {
+    let resolve = |link: &str| match url::Url::parse(link) {
+        Err(url::ParseError::RelativeUrlWithoutBase) => base.join(link).ok().map(String::from),
+        _ => None,
+    };
+
+    if let Some(link) = item.link().and_then(resolve) {
+        item.set_link(link);
+    }
+    if let Some(enclosure) = item.enclosure.as_mut() {
+        if let Some(url) = resolve(enclosure.url()) {
+            enclosure.set_url(url);
+        }
+    }
+}
+
+/// Keep `max` entries, taking them in turn from each channel
+///
+/// Each round takes the next entry of every channel, in the order of the entries. The kept
+/// entries stay in their original order.
+fn fair_truncate(items: &mut Vec<(usize, Item)>, max: usize) {
+    let mut positions: Vec<Vec<usize>> = Vec::new();
+    for (position, (source, _)) in items.iter().enumerate() {
+        if positions.len() <= *source {
+            positions.resize_with(source + 1, Vec::new);
+        }
+        positions[*source].push(position);
+    }
+
+    let mut keep = vec![false; items.len()];
+    let mut nr_kept = 0;
+    for round in 0.. {
+        let mut candidates: Vec<usize> = positions.iter()
+            .filter_map(|positions| positions.get(round).copied())
+            .collect();
+        candidates.sort_unstable();
+        for position in candidates.into_iter().take(max - nr_kept) {
+            keep[position] = true;
+            nr_kept += 1;
+        }
+        if nr_kept >= max {
+            break
+        }
+    }
+
+    let mut keep = keep.into_iter();
+    items.retain(|_| keep.next().unwrap_or(false));
+}
+
+/// Shorten a text to at most `max` characters including the ellipsis put at the end, returns
+/// None if it is short enough already
+///
+/// Characters are counted instead of bytes, so multibyte characters are never split.
+pub fn truncate_text(text: &str, max: usize) -> Option<String> {
+    text.char_indices().nth(max)?;
+    let end = text.char_indices().nth(max.saturating_sub(1)).map_or(text.len(), |(end, _)| end);
+    let mut truncated = text[..end].trim_end().to_string();
+    truncated.push('…');
+    Some(truncated)
+}
+
+/// Convert HTML into plain text
+///
+/// Tags are removed, as are scripts and stylesheets, and entities are decoded. Whitespace is
+/// collapsed into single spaces. Any < and & in the text are escaped again as `&lt;` and `&amp;`,
+/// so that a decoded `&lt;b&gt;` is not taken for a tag and already stripped text comes out the
+/// same.
+pub fn strip_html(html: &str) -> String {
+    fn push_escaped(text: &mut String, c: char) {
+        match c {
+            '<' => text.push_str("&lt;"),
+            '&' => text.push_str("&amp;"),
+            c => text.push(c),
+        }
+    }
+
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find(['<', '&']) {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with('&') {
+            let entity = rest[1..].find(';').filter(|&end| end <= 10).and_then(|end| {
+                let name = &rest[1..end + 1];
+                let c = match name {
+                    "amp" => Some('&'),
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    "nbsp" => Some(' '),
+                    _ => name.strip_prefix("#x").or_else(|| name.strip_prefix("#X"))
+                        .map(|hex| u32::from_str_radix(hex, 16))
+                        .or_else(|| name.strip_prefix('#').map(str::parse))
+                        .and_then(|code| code.ok())
+                        .and_then(char::from_u32),
+                };
+                c.map(|c| (c, end + 2))
+            });
+            match entity {
+                Some((c, len)) => {
+                    push_escaped(&mut text, c);
+                    rest = &rest[len..];
+                }
+                None => {
+                    push_escaped(&mut text, '&');
+                    rest = &rest[1..];
+                }
+            }
+            continue
+        }
+
+        let is_tag = rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!');
+        let end = match rest.find('>') {
+            Some(end) if is_tag => end,
+            _ => {
+                push_escaped(&mut text, '<');
+                rest = &rest[1..];
+                continue
+            }
+        };
+
+        // Skip the contents of scripts and stylesheets
+        let name = rest[1..end].split(|c: char| c.is_whitespace() || c == '/' || c == '>')
+            .next().unwrap_or_default().to_ascii_lowercase();
+        rest = &rest[end + 1..];
+        if name == "script" || name == "style" {
+            let closing = format!("</{}", name);
+            rest = rest.to_ascii_lowercase().find(&closing)
+                .and_then(|start| rest[start..].find('>').map(|end| &rest[start + end + 1..]))
+                .unwrap_or("");
+        }
+
+        // Tags might separate words
+        text.push(' ');
+    }
+    text.push_str(rest);
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Date formats without a timezone that are seen in the wild, assumed to be UTC
+const LOOSE_DATE_FORMATS: &[&str] = &["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"];
+
+/// Parse an entry date, returns None if it is invalid
+///
+/// RSS requires RFC 2822, but RFC 3339 and a few looser formats are accepted as well.
+pub fn parse_item_date(date: &str) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    let date = date.trim();
+    if let Ok(parsed) = rfc822_sanitizer::parse_from_rfc2822_with_fallback(date) {
+        return Some(parsed);
+    }
+    if let Ok(parsed) = chrono::DateTime::parse_from_rfc3339(date) {
+        return Some(parsed);
+    }
+    if let Ok(parsed) = chrono::DateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S %z") {
+        return Some(parsed);
+    }
+    let utc = chrono::FixedOffset::east_opt(0)?;
+    LOOSE_DATE_FORMATS
+        .iter()
+        .find_map(|format| chrono::NaiveDateTime::parse_from_str(date, format).ok())
+        .or_else(|| chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))
+        .map(|naive| naive.and_utc().with_timezone(&utc))
+}
+
+/// Merge the entries of the extra channels into the main channel
+///
+/// The channel fields are taken from the channel chosen by `primary`, by default the last extra one.
+/// The image is taken from the main channel, unless `image_from` says otherwise.
+pub fn combine(main: Channel, extras: Vec<Channel>, opts: &CombineOptions) -> Channel {
+    combine_with_stats(main, extras, opts).0
+}
+
+/// Like [`combine`], but also returns what happened during the merge
+pub fn combine_with_stats(mut main: Channel, extras: Vec<Channel>, opts: &CombineOptions) -> (Channel, Stats) {
+    // Keep a list of known GUIDs to prevent duplicate RSS entries
+    //
+    // Each maps to the channel the entry came from, to report what a duplicate collided with
+    let mut known_guids = HashMap::new();
+    let mut known_hashes = HashMap::new();
+    let mut known_enclosures = HashMap::new();
+    let mut known_media = HashMap::new();
+
+    // Highest comment count of the duplicates of each kept entry, for `merge_comments`
+    let mut comment_counts: HashMap<String, u64> = HashMap::new();
+    // Newest duplicate of each kept entry, for `merge_update`
+    let mut updates: HashMap<String, (chrono::DateTime<chrono::FixedOffset>, Item)> = HashMap::new();
+
+    // Keep track of what happens during the merge. This includes the number of RSS entries without
+    // a GUID (or whichever field is used as dedup key), this to warn the user as the GUID is used
+    // to merge the entries
+    //
+    // It can indicate a problem with the RSS feed
+    let mut stats = Stats::default();
+
+    // Two lists:
+    // a) list of original RSS entries
+    // b) list of new RSS entries
+    let mut items_orig = std::mem::take(&mut main.items);
+    let mut items_extra = Vec::new();
+
+    if let Some(prefix) = opts.prefix_title.as_deref().filter(|_| opts.prefix_all) {
+        for item in items_orig.iter_mut() {
+            prefix_title(item, prefix, main.title());
+        }
+    }
+
+    // The links are relative to the channel they came from
+    let link_base = |channel: &Channel| match &opts.base_url {
+        Some(base_url) => Some(base_url.clone()),
+        None if opts.absolute_links => url::Url::parse(channel.link()).ok(),
+        None => None,
+    };
+    if let Some(base) = link_base(&main) {
+        for item in items_orig.iter_mut() {
+            absolutize_links(item, &base);
+        }
+    }
+
+    stats.main_items = items_orig.len();
+    let main_media = media_prefix(&main).to_string();
+    if opts.title_dedup && !opts.append_only {
+        for title in dedup_titles(&mut items_orig) {
+            stats.dropped_title += 1;
+            stats.add_duplicate(&title, 0, 0, DuplicateOf::Title);
+        }
+    }
+    for item in items_orig.iter() {
+        // This logic will remove any RSS items without an GUID
+        if let Some(guid) = dedup_key(item, opts.dedup_key) {
+            let guid = opts.compare_key(guid);
+            if known_guids.contains_key(&guid) && !stats.main_repeated_guids.contains(&guid) {
+                stats.main_repeated_guids.push(guid.clone());
+            }
+            known_guids.insert(guid, 0);
+        } else if !opts.keep_guidless {
+            stats.missing_guids += 1;
+        }
+
+        if opts.dedup_by_content || opts.keep_guidless {
+            known_hashes.extend(content_hash(item).map(|hash| (hash, 0)));
+        }
+
+        if opts.dedup_by_enclosure {
+            known_enclosures.extend(item.enclosure().map(|enclosure| (enclosure.url().to_string(), 0)));
+        }
+
+        if opts.dedup_by_media_url {
+            known_media.extend(media_url(item, &main_media).map(|url| (url.to_string(), 0)));
+        }
+    }
+
+    // Remember where each entry came from, the main channel being 0
+    let mut items_orig: Vec<_> = items_orig.into_iter().map(|item| (0, item)).collect();
+
+    // The channel variable is reused so that the merged RSS contains the fields from the newest
+    // RSS file, unless those of the main channel are wanted
+    let mut channel = main;
+    let main_channel = if opts.primary == Primary::First { Some(channel.clone()) } else { None };
+    let main_image = channel.image().cloned();
+
+    // Entries can use extensions declared by any of the channels
+    let mut namespaces = channel.namespaces.clone();
+    let main_ttl = channel.ttl().and_then(|ttl| ttl.trim().parse::<u32>().ok());
+    let mut min_ttl = main_ttl;
+
+    // Excludes always win over includes
+    let case_insensitive = opts.category_case_insensitive;
+    let category_rejected = |item: &Item| {
+        (!opts.include_categories.is_empty() && !has_category(item, &opts.include_categories, case_insensitive))
+            || has_category(item, &opts.exclude_categories, case_insensitive)
+    };
+    let title_rejected = |item: &Item| {
+        let title = item.title().unwrap_or_default();
+        opts.title_match.as_ref().is_some_and(|title_match| !title_match.is_match(title))
+            || opts.title_reject.as_ref().is_some_and(|title_reject| title_reject.is_match(title))
+    };
+
+    for (source, mut extra) in (1..).zip(extras) {
+        let mut vec_items = std::mem::take(&mut extra.items);
+        stats.file_items.push(vec_items.len());
+        if opts.title_dedup {
+            for title in dedup_titles(&mut vec_items) {
+                stats.add_duplicate(&title, source, source, DuplicateOf::Title);
+            }
+        }
+        if let Some(ttl) = extra.ttl().and_then(|ttl| ttl.trim().parse::<u32>().ok()) {
+            min_ttl = Some(min_ttl.map_or(ttl, |min_ttl| min_ttl.min(ttl)));
+        }
+        namespaces.extend(extra.namespaces.iter().map(|(prefix, uri)| (prefix.clone(), uri.clone())));
+        channel = extra;
+
+        // Number of new entries taken from this channel
+        let mut nr_new = 0;
+        let base = link_base(&channel);
+        let source_element = channel_source(&channel).filter(|_| opts.add_source);
+        let media_ns = media_prefix(&channel).to_string();
+
+        // A broken RSS file might repeat the same GUID, separately track the GUIDs of this file
+        let mut file_guids = HashSet::new();
+        let mut repeated_guids = Vec::new();
+
+        for mut item in vec_items {
+            // Before determining the dedup key, as the link might be used for that
+            if let Some(base) = &base {
+                absolutize_links(&mut item, base);
+            }
+
+            let guid = match dedup_key(&item, opts.dedup_key) {
+                Some(guid) => Some(opts.compare_key(guid)),
+                None if opts.keep_guidless => None,
+                None       => {
+                    stats.missing_guids += 1;
+                    continue;
+                }
+            };
+
+            if let Some(guid) = &guid {
+                if !file_guids.insert(guid.clone()) && !repeated_guids.contains(guid) {
+                    repeated_guids.push(guid.clone());
+                }
+
+                if let Some(&earlier_source) = known_guids.get(guid) {
+                    stats.add_duplicate(guid, source, earlier_source, DuplicateOf::Key);
+                    if let Some(count) = comment_count(&item).filter(|_| opts.merge_comments) {
+                        let max_count = comment_counts.entry(guid.clone()).or_default();
+                        *max_count = count.max(*max_count);
+                    }
+                    // The newer copy has to pass the same filters as a new entry
+                    let date = item.pub_date().and_then(parse_item_date)
+                        .filter(|_| opts.merge_update && !category_rejected(&item) && !title_rejected(&item));
+                    if let Some(date) = date.filter(|&date| updates.get(guid).is_none_or(|(newest, _)| date > *newest)) {
+                        if let Some(prefix) = &opts.prefix_title {
+                            prefix_title(&mut item, prefix, channel.title());
+                        }
+                        if let Some(source_element) = &source_element {
+                            item.set_source(source_element.clone());
+                        }
+                        updates.insert(guid.clone(), (date, item));
+                    }
+                    continue
+                }
+
+                if opts.seen_guids.contains(guid) {
+                    stats.seen_before += 1;
+                    continue
+                }
+            }
+
+            if category_rejected(&item) {
+                stats.filtered_category += 1;
+                continue
+            }
+
+            if title_rejected(&item) {
+                stats.filtered_title += 1;
+                continue
+            }
+
+            // Without a GUID this is the only way to recognize entries merged before
+            if opts.dedup_by_content || guid.is_none() {
+                if let Some(hash) = content_hash(&item) {
+                    if let Some(&earlier_source) = known_hashes.get(&hash) {
+                        let key = guid.as_deref().or(item.title()).unwrap_or_default();
+                        stats.add_duplicate(key, source, earlier_source, DuplicateOf::Content);
+                        continue
+                    }
+                    known_hashes.insert(hash, source);
+                }
+            }
+
+            let enclosure = item.enclosure().map(|enclosure| enclosure.url().to_string())
+                .filter(|_| opts.dedup_by_enclosure);
+            if let Some(&earlier_source) = enclosure.as_ref().and_then(|url| known_enclosures.get(url)) {
+                let key = guid.as_deref().or(item.title()).unwrap_or_default();
+                stats.add_duplicate(key, source, earlier_source, DuplicateOf::Enclosure);
+                continue
+            }
+
+            let media = media_url(&item, &media_ns).map(str::to_string).filter(|_| opts.dedup_by_media_url);
+            if let Some(&earlier_source) = media.as_ref().and_then(|url| known_media.get(url)) {
+                let key = guid.as_deref().or(item.title()).unwrap_or_default();
+                stats.add_duplicate(key, source, earlier_source, DuplicateOf::Media);
+                continue
+            }
+
+            if opts.limit_per_source > 0 && nr_new >= opts.limit_per_source {
+                stats.dropped_per_source += 1;
+                continue
+            }
+            nr_new += 1;
+
+            if let Some(prefix) = &opts.prefix_title {
+                prefix_title(&mut item, prefix, channel.title());
+            }
+            if let Some(source_element) = &source_element {
+                item.set_source(source_element.clone());
+            }
+
+            known_enclosures.extend(enclosure.map(|url| (url, source)));
+            known_media.extend(media.map(|url| (url, source)));
+            if let Some(guid) = guid {
+                known_guids.insert(guid.clone(), source);
+                stats.new_guids.push(guid);
+            }
+            items_extra.push((source, item));
+        }
+
+        stats.file_repeated_guids.push(repeated_guids);
+    }
+
+    stats.new_items = items_extra.len();
+
+    // Only replace entries having a date, so it is known the copy is newer
+    if !updates.is_empty() {
+        for (_, item) in items_orig.iter_mut().chain(items_extra.iter_mut()) {
+            let Some(date) = item.pub_date().and_then(parse_item_date) else { continue };
+            let update = dedup_key(item, opts.dedup_key)
+                .and_then(|guid| updates.remove(&opts.compare_key(guid)))
+                .filter(|(newest, _)| *newest > date);
+            if let Some((_, update)) = update {
+                *item = update;
+                stats.updated_items += 1;
+            }
+        }
+    }
+
+    if !comment_counts.is_empty() {
+        for (_, item) in items_orig.iter_mut().chain(items_extra.iter_mut()) {
+            let count = dedup_key(item, opts.dedup_key)
+                .and_then(|guid| comment_counts.get(&opts.compare_key(guid)))
+                .copied();
+            if let Some(count) = count.filter(|&count| comment_count(item).is_none_or(|own| own < count)) {
+                set_comment_count(item, count);
+            }
+        }
+        namespaces.entry("slash".to_string()).or_insert_with(|| SLASH_NAMESPACE.to_string());
+    }
+
+    // Drop the entries older than the cutoff, combining both ways to specify it
+    let now = opts.now.unwrap_or_else(|| chrono::Utc::now().fixed_offset());
+    let expiry = opts.max_age.map(|max_age| now - max_age);
+    if let Some(since) = opts.since.into_iter().chain(expiry).max() {
+        let is_recent = |(_, item): &(usize, Item)| match item.pub_date().and_then(parse_item_date) {
+            Some(date) => date >= since,
+            None => !opts.drop_undated,
+        };
+        let nr_items = items_extra.len() + items_orig.len();
+        items_extra.retain(is_recent);
+        if !opts.append_only {
+            items_orig.retain(is_recent);
+        }
+        stats.dropped_old = nr_items - items_extra.len() - items_orig.len();
+    }
+
+    if let Some((prefix, name)) = &opts.expiry_element {
+        let is_current = |(_, item): &(usize, Item)| item.extensions().get(prefix)
+            .and_then(|extensions| extensions.get(name))
+            .and_then(|extensions| extensions.first())
+            .and_then(|extension| extension.value())
+            .and_then(parse_item_date)
+            .is_none_or(|expires| expires > now);
+        let nr_items = items_extra.len() + items_orig.len();
+        items_extra.retain(is_current);
+        if !opts.append_only {
+            items_orig.retain(is_current);
+        }
+        stats.dropped_expired = nr_items - items_extra.len() - items_orig.len();
+    }
+
+    // Combine all entries into items_extra
+    if opts.insert == Insert::Bottom {
+        std::mem::swap(&mut items_extra, &mut items_orig);
+    }
+    items_extra.append(&mut items_orig); // this clears items_orig
+
+    // Sort newest first so that truncating keeps the newest entries, regardless of the order used
+    // within each RSS file. Entries without a (valid) date end up last.
+    //
+    // The sort is stable, entries with the same date keep their order: new entries before the
+    // main entries (or after with `Insert::Bottom`) and within each RSS file the order of that
+    // file.
+    if let Some(seed) = opts.shuffle {
+        use rand::SeedableRng;
+        use rand::seq::SliceRandom;
+        items_extra.shuffle(&mut rand::rngs::StdRng::seed_from_u64(seed));
+    } else if opts.sort {
+        use std::cmp::Reverse;
+        items_extra.sort_by_cached_key(|(_, item)| Reverse(item.pub_date().and_then(parse_item_date)));
+    }
+
+    // The number of entries is always limited, also when the main RSS alone is too big, unless
+    // the main RSS is an archive
+    let max_entries = match opts.keep_percent {
+        Some(percent) => Some((items_extra.len() * usize::from(percent) + 50) / 100),
+        None => Some(opts.max_entries).filter(|&max_entries| max_entries > 0),
+    };
+    if let Some(max_entries) = max_entries.filter(|&max_entries| !opts.append_only && items_extra.len() > max_entries) {
+        stats.dropped_max_entries = items_extra.len() - max_entries;
+        if opts.fair_truncate {
+            fair_truncate(&mut items_extra, max_entries);
+        } else if opts.insert == Insert::Bottom && !opts.sort && opts.shuffle.is_none() {
+            items_extra.drain(..items_extra.len() - max_entries);
+        } else {
+            items_extra.truncate(max_entries);
+        }
+    }
+
+    let last_image = channel.image().cloned();
+    if let Some(main_channel) = main_channel {
+        channel = main_channel;
+    }
+    channel.set_image(match opts.image_from {
+        ImageFrom::Main => main_image,
+        ImageFrom::Last => last_image,
+        ImageFrom::None => None,
+    });
+    if let Some(ttl) = opts.ttl {
+        let ttl = match ttl {
+            Ttl::Auto => min_ttl,
+            Ttl::Keep => main_ttl,
+            Ttl::Minutes(minutes) => Some(minutes),
+        };
+        channel.set_ttl(ttl.map(|ttl| ttl.to_string()));
+    }
+
+    // Add the entries back to the RSS feed
+    if opts.reverse {
+        items_extra.reverse();
+    }
+    stats.new_positions = items_extra.iter().enumerate()
+        .filter(|(_, (source, _))| *source > 0)
+        .map(|(position, _)| position)
+        .collect();
+    let mut items: Vec<_> = items_extra.into_iter().map(|(_, item)| item).collect();
+    if let Some(author) = &opts.default_author {
+        for item in items.iter_mut().filter(|item| item_author(item).is_none()) {
+            item.set_author(author.clone());
+        }
+    }
+    if opts.strip_html {
+        for item in items.iter_mut() {
+            if let Some(description) = item.description().map(strip_html) {
+                item.set_description(description);
+            }
+        }
+    }
+    if let Some(max) = opts.max_description_chars.filter(|&max| max > 0) {
+        for item in items.iter_mut() {
+            let description = item.description()
+                .filter(|description| opts.strip_html || !description.contains('<'))
+                .and_then(|description| truncate_text(description, max));
+            if let Some(mut description) = description {
+                // Do not cut a `&lt;` or `&amp;` of the stripped text in half
+                if opts.strip_html {
+                    if let Some(amp) = description.rfind('&').filter(|&amp| !description[amp..].contains(';')) {
+                        description.truncate(amp);
+                        description.truncate(description.trim_end().len());
+                        description.push('…');
+                    }
+                }
+                item.set_description(description);
+            }
+        }
+    }
+    channel.set_items(items);
+
+    // The declarations of the resulting channel win in case a prefix is used for different URIs
+    namespaces.append(&mut channel.namespaces);
+    channel.namespaces = namespaces;
+
+    (channel, stats)
+}
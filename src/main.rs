@@ -1,162 +1,2704 @@
-use std::path::PathBuf;
-use clap::Parser;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use rss::{Channel, Guid, Item};
+use rss_combine::{combine, combine_with_stats, dedup_key, item_author, parse_item_date, CombineOptions, DedupKey, DuplicateOf, ImageFrom, Insert, Primary, Stats, Ttl};
 
 
+/// Without a subcommand the options of combine are accepted, as before subcommands existed
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true,
+          subcommand_negates_reqs = true, after_help = EXIT_STATUS_HELP)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Print a completion script for this shell to stdout, see the README
+    #[arg(long, value_enum, value_name = "SHELL", hide = true, exclusive = true)]
+    generate_completions: Option<clap_complete::Shell>,
+
+    #[command(flatten)]
+    combine: Opt,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Merge the entries of the additional files into the main RSS file (the default)
+    Combine(Box<Opt>),
+    /// Parse all files and report any errors, without writing anything
+    Validate(InspectOpt),
+    /// Print the GUID, date and title of the entries of all files, for debugging
+    Dump(InspectOpt),
+}
+
+#[derive(Args, Debug)]
 struct Opt {
     /// Maximum number of entries in the RSS; use 0 for unlimited entries
     #[arg(short = 'l', default_value = "0")]
     max_entries: usize,
 
-    /// Print more details to stdout
+    /// Keep this percentage of the combined entries instead of a fixed maximum, the newest ones
+    /// when sorting. Rounded to the nearest number of entries, halves upwards; 0 keeps no entries
+    /// at all and 100 keeps all
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100), value_name = "0-100",
+          conflicts_with_all = ["max_entries", "append_only"])]
+    keep_percent: Option<u8>,
+
+    /// Do not write the merged RSS if it would have fewer entries than this, e.g. because all
+    /// additional files failed; must not exceed -l
+    #[arg(long, default_value = "0")]
+    min_entries: usize,
+
+    /// What to do when the merged RSS has no entries at all, whether or not the main RSS had any:
+    /// fail with an error, keep the output as it is or write it without entries. Nothing is kept
+    /// when writing to stdout, so keep writes nothing then.
+    #[arg(long, value_enum, default_value = "keep")]
+    on_empty: OnEmpty,
+
+    /// Drop the oldest entries until the merged RSS is at most this many bytes; use 0 for no limit.
+    /// Cannot be used with --append-only, as that never drops the entries of the main RSS
+    #[arg(long, default_value = "0", value_name = "N", conflicts_with = "append_only")]
+    max_bytes: usize,
+
+    /// Warn when the merged RSS is more than this many bytes, an error with --strict; unlike
+    /// --max-bytes no entries are dropped
+    #[arg(long, value_name = "BYTES")]
+    warn_size: Option<usize>,
+
+    /// Maximum number of new entries taken from each additional file; use 0 for unlimited entries
+    #[arg(long, default_value = "0")]
+    limit_per_source: usize,
+
+    /// When limiting the number of entries with -l, take them in turn from each file so that no
+    /// single file takes all places; the entries of the main RSS file count as one file
+    #[arg(long)]
+    fair_truncate: bool,
+
+    #[command(flatten)]
+    read: ReadOpt,
+
+    /// Write the merged RSS to this file instead of back into the main RSS file
+    #[arg(short, long, value_parser)]
+    output: Option<PathBuf>,
+
+    /// Write the merged RSS to stdout; verbose output then goes to stderr
+    #[arg(long, conflicts_with = "output")]
+    stdout: bool,
+
+    /// Format of the merged file
+    #[arg(long, value_enum, default_value = "rss")]
+    output_format: OutputFormat,
+
+    /// Field used to detect duplicate entries [default: guid, falling back to link]
+    #[arg(long, value_enum)]
+    dedup_key: Option<DedupKey>,
+
+    /// Take the title, link, description and other channel fields from the main RSS file (first)
+    /// or the last additional file (last); the order of the entries is not affected
+    #[arg(long, value_enum, default_value = "last")]
+    primary: Primary,
+
+    /// Same as --primary first
+    #[arg(long, conflicts_with = "primary")]
+    keep_main_metadata: bool,
+
+    /// Take the channel image from the main RSS file, the last additional file or leave it out
+    #[arg(long, value_enum, default_value = "main")]
+    image_from: ImageFrom,
+
+    /// Set the ttl of the merged RSS in minutes; auto takes the lowest ttl of all files, keep the
+    /// one of the main RSS file [default: the ttl of wherever the channel fields come from]
+    #[arg(long, value_parser = parse_ttl, value_name = "auto|keep|MINUTES")]
+    ttl: Option<Ttl>,
+
+    /// Pass each entry of the additional files through this shell command. It gets a RSS file with
+    /// just that entry on stdin and should write the changed RSS file to stdout, or exit with a
+    /// non-zero status to drop the entry. The command runs once per entry, so this is slow for
+    /// large files
+    #[arg(long, value_name = "CMD")]
+    filter_cmd: Option<String>,
+
+    /// Take the title, link, description and other channel fields from this RSS file instead of
+    /// from any of the merged files; any entries in it are ignored
+    #[arg(long, value_parser, value_name = "FILE", conflicts_with_all = ["keep_main_metadata", "primary"])]
+    channel_template: Option<PathBuf>,
+
+    /// Drop entries published before this date; either RFC 3339 (2024-01-31T00:00:00Z) or
+    /// relative to now in hours, days or weeks (24h, 7d, 2w)
+    #[arg(long, value_parser = parse_since)]
+    since: Option<Since>,
+
+    /// Drop entries older than this on every run, in hours, days or weeks (24h, 30d, 2w)
+    #[arg(long, value_parser = parse_duration)]
+    max_age: Option<chrono::Duration>,
+
+    /// Also drop entries without a (valid) date when using --since or --max-age
+    #[arg(long)]
+    drop_undated: bool,
+
+    /// Drop entries once the date in this extension element has passed, e.g. x:expires using the
+    /// prefix of the namespace in the RSS file; entries without it are kept
+    #[arg(long, value_parser = parse_element, value_name = "PREFIX:NAME")]
+    expiry_element: Option<(String, String)>,
+
+    /// Treat the main RSS file as an archive: its entries are never dropped and the maximum
+    /// number of entries is ignored
+    #[arg(long, conflicts_with = "max_entries")]
+    append_only: bool,
+
+    /// Only merge new entries with this category; can be given multiple times
+    #[arg(long = "include-category", value_name = "NAME")]
+    include_categories: Vec<String>,
+
+    /// Never merge new entries with this category; can be given multiple times
+    #[arg(long = "exclude-category", value_name = "NAME")]
+    exclude_categories: Vec<String>,
+
+    /// Ignore case when matching --include-category and --exclude-category
+    #[arg(long)]
+    category_case_insensitive: bool,
+
+    /// Only merge new entries with a title matching this regular expression; use (?i) to ignore
+    /// case
+    #[arg(long, value_name = "REGEX")]
+    title_match: Option<regex::Regex>,
+
+    /// Never merge new entries with a title matching this regular expression
+    #[arg(long, value_name = "REGEX")]
+    title_reject: Option<regex::Regex>,
+
+    /// Write the merged file without any indentation or line breaks [default when --stdout is
+    /// not a terminal]
+    #[arg(long, visible_alias = "no-pretty")]
+    compact: bool,
+
+    /// Indent the merged file [default when writing a file or to a terminal]
+    #[arg(long, conflicts_with = "compact")]
+    pretty: bool,
+
+    /// Use Windows line endings (CRLF) in the merged file
+    #[arg(long)]
+    crlf: bool,
+
+    /// Number of spaces used for indenting the merged file
+    #[arg(long, default_value = "2", conflicts_with = "compact")]
+    indent: usize,
+
+    /// Put this before the title of entries from additional files; %name is replaced by the title
+    /// of the file they came from, e.g. "[%name] "
+    #[arg(long, value_name = "PREFIX")]
+    prefix_title: Option<String>,
+
+    /// Also put --prefix-title before the titles of the entries in the main RSS file
+    #[arg(long, requires = "prefix_title")]
+    prefix_all: bool,
+
+    /// Remember the GUIDs of all merged entries in this file, one per line, and never merge them
+    /// again; this prevents old entries from coming back after they were dropped
+    #[arg(long, value_parser, value_name = "FILE")]
+    seen_db: Option<PathBuf>,
+
+    /// Maximum number of GUIDs kept in --seen-db, dropping the oldest; use 0 for unlimited
+    #[arg(long, default_value = "10000", requires = "seen_db")]
+    seen_db_max: usize,
+
+    /// Turn the HTML descriptions of the entries into plain text
+    #[arg(long)]
+    strip_html: bool,
+
+    /// Shorten descriptions longer than this many characters, ending them with an ellipsis;
+    /// descriptions containing HTML are left alone unless --strip-html is used. Use 0 for no limit
+    #[arg(long, value_name = "N")]
+    max_description_chars: Option<usize>,
+
+    /// Resolve relative links and enclosure URLs of entries against the link of the RSS file they
+    /// came from
+    #[arg(long)]
+    absolute_links: bool,
+
+    /// Resolve relative links and enclosure URLs of all entries against this URL
+    #[arg(long, value_name = "URL")]
+    base_url: Option<url::Url>,
+
+    /// Set this as author of the entries lacking one
+    #[arg(long, value_name = "NAME")]
+    default_author: Option<String>,
+
+    /// For entries found in several RSS files, keep the highest <slash:comments> count instead of
+    /// the one of the entry that is kept
+    #[arg(long)]
+    merge_comments: bool,
+
+    /// For entries found in several RSS files, keep the copy with the newest date instead of the
+    /// first one, e.g. for corrected entries; entries without a date are never replaced
+    #[arg(long)]
+    merge_update: bool,
+
+    /// Set the <source> of new entries to the title and URL of the RSS file they came from,
+    /// instead of keeping any existing <source>
+    #[arg(long)]
+    add_source: bool,
+
+    /// When the output file is a symlink, replace the symlink itself instead of the file it points
+    /// to
+    #[arg(long)]
+    no_follow_symlinks: bool,
+
+    /// Write statistics about the merge as JSON to this file, use - for stdout; also done for
+    /// --dry-run
+    #[arg(long, value_parser, value_name = "FILE")]
+    stats_json: Option<PathBuf>,
+
+    /// Merge without writing anything, only report what would change
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+
+    /// Also write the merged entries into one file per category within this directory, named
+    /// after their first category; entries without a category go into uncategorized.xml. -l and
+    /// --keep-percent apply to each file separately, so a file can have entries which were dropped
+    /// from the merged RSS
+    #[arg(long, value_parser, value_name = "DIR")]
+    split_by_category: Option<PathBuf>,
+
+    /// Also write the entries merged by this run into this file, with the channel fields of the
+    /// main RSS; it is written on every run, so it can be empty
+    #[arg(long, value_parser, value_name = "FILE")]
+    only_new: Option<PathBuf>,
+
+    /// Write the SHA-256 of the merged RSS in hex to this file, after the merged RSS itself was
+    /// written
+    #[arg(long, value_parser, value_name = "FILE")]
+    checksum: Option<PathBuf>,
+
+    /// Print a line to stderr for each entry skipped as duplicate, with tab separated its GUID, the
+    /// file it came from, the file with the earlier entry and what matched (key, content,
+    /// enclosure, title or media)
+    #[arg(long)]
+    dedup_report: bool,
+
+    /// Use this RFC 3339 date as the current time, for reproducible results when testing
+    #[arg(long, env = "RSS_COMBINE_NOW", hide = true)]
+    now: Option<chrono::DateTime<chrono::FixedOffset>>,
+
+    /// Only print the number of entries of each file and the number of unique entries of all
+    /// files together, without merging or writing anything
+    #[arg(long)]
+    count_only: bool,
+
+    /// Leave lastBuildDate and pubDate of the channel untouched when rewriting
+    #[arg(long)]
+    no_update_builddate: bool,
+
+    /// Skip checking that the merged RSS can be parsed again before writing it
+    #[arg(long)]
+    no_verify: bool,
+
+    /// Do not show a progress bar when reading more than a few additional files
+    #[arg(long)]
+    no_progress: bool,
+
+    /// Read and parse this many additional files at the same time; 0 uses one per CPU. The
+    /// result is the same as when reading them one by one.
+    #[arg(short, long, default_value = "1", value_name = "N")]
+    jobs: usize,
+
+    /// Exit with status 10 instead of 0 when the merged RSS changed
+    #[arg(long)]
+    exit_code_on_change: bool,
+
+    /// Normalize GUIDs before comparing them: trim whitespace, strip a trailing / and lowercase
+    /// the scheme and host of URLs
+    #[arg(long)]
+    normalize_guid: bool,
+
+    /// Ignore case when comparing GUIDs or whichever field is used with --dedup-key; this can
+    /// wrongly merge entries of feeds where the case of GUIDs matters
+    #[arg(long)]
+    dedup_ignore_case: bool,
+
+    /// Also treat entries with the same title and link as duplicates, even if their GUIDs differ.
+    /// This catches feeds which rotate GUIDs, but can wrongly merge distinct entries sharing a
+    /// generic title and link
+    #[arg(long)]
+    dedup_by_content: bool,
+
+    /// Also treat entries with the same enclosure URL as duplicates, even if their GUIDs differ;
+    /// useful when the same podcast episode is in multiple feeds
+    #[arg(long)]
+    dedup_by_enclosure: bool,
+
+    /// Also treat entries with the same <media:content> URL as duplicates, even if their GUIDs
+    /// differ; useful for video and image feeds
+    #[arg(long)]
+    dedup_by_media_url: bool,
+
+    /// Within each file, only keep the newest of the entries with exactly the same title, for
+    /// feeds republishing an entry with a different GUID; not done for the main RSS with
+    /// --append-only
+    #[arg(long)]
+    title_dedup: bool,
+
+    /// Merge new entries without a GUID (or whichever field --dedup-key uses) instead of ignoring
+    /// them; such entries are recognized as duplicates by their title and link
+    #[arg(long)]
+    keep_guidless: bool,
+
+    /// Keep the merged entries in append order instead of sorting them newest first
+    #[arg(long)]
+    no_sort: bool,
+
+    /// Put new entries before (top) or after (bottom) the entries of the main RSS, which keep
+    /// their order. When sorting this only matters for entries with the same date; with
+    /// --no-sort and bottom, -l drops the entries at the top.
+    #[arg(long, value_enum, default_value = "top")]
+    insert: Insert,
+
+    /// Put the entries in a random order instead of sorting them, optionally using SEED to get
+    /// the same order every time (--shuffle=SEED). With -l this keeps a random selection of the
+    /// entries
+    #[arg(long, value_name = "SEED", num_args = 0..=1, require_equals = true, conflicts_with = "no_sort")]
+    shuffle: Option<Option<u64>>,
+
+    /// Write the entries in reverse order, e.g. oldest first. -l still keeps the newest entries,
+    /// only the order of the kept entries is reversed
+    #[arg(long)]
+    reverse: bool,
+
+    /// Read the options from this TOML file; options given on the command line take precedence
+    #[arg(long, value_parser)]
+    config: Option<PathBuf>,
+
+    /// Main RSS file or http(s) URL; use - to read it from stdin (URL and stdin require --output
+    /// or --stdout)
+    #[arg(value_parser, required_unless_present = "config")]
+    input: Option<PathBuf>,
+
+    /// Read additional files or URLs from this file, one per line; blank lines and lines
+    /// starting with # are ignored
+    #[arg(long, value_parser)]
+    feeds_from: Option<PathBuf>,
+
+    /// Additional files or http(s) URLs; glob patterns like feeds/*.xml are expanded, as are the
+    /// *.xml and *.rss files in directories and zip archives. Append :auto, :rss or :atom to
+    /// override --input-format for a single file
+    #[arg(value_parser, required_unless_present_any = ["feeds_from", "config"])]
+    files: Vec<PathBuf>,
+
+}
+
+/// Options for reading RSS files, shared by all subcommands
+#[derive(Args, Debug)]
+struct ReadOpt {
+    /// Print more details to stderr; RUST_LOG can be used instead for more control
     #[arg(short, long)]
     verbose: bool,
 
-    /// Main RSS file
-    #[arg(value_parser)]
-    input: PathBuf,
+    /// Do not print any warnings, nor the "rss-combine: wrote N items (M new)" line printed to
+    /// stderr after writing the merged RSS
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Timeout in seconds when fetching RSS files over HTTP(S)
+    #[arg(long, default_value = "30")]
+    timeout: u64,
+
+    /// Maximum number of redirects to follow when fetching an URL
+    #[arg(long, default_value = "5")]
+    max_redirects: u32,
+
+    /// Number of times to retry fetching an URL after a server or connection error
+    #[arg(long, default_value = "2")]
+    retries: u32,
+
+    /// Format of the input files
+    #[arg(long, value_enum, default_value = "auto")]
+    input_format: InputFormat,
+
+    /// Fail instead of warning when an additional file or pattern is skipped, entries lack a
+    /// GUID or a file repeats a GUID
+    #[arg(long)]
+    strict: bool,
+
+    /// Remember the responses of URLs in this directory, then only fetch them again when the
+    /// server says they changed (using ETag and Last-Modified)
+    #[arg(long, value_parser, value_name = "DIR")]
+    http_cache: Option<PathBuf>,
+
+    /// Also include the RSS files in subdirectories of directories given as additional files
+    #[arg(short, long)]
+    recursive: bool,
+
+    /// Order of the files found by a glob pattern or within a directory: by name, by
+    /// modification time (oldest first, so the channel fields come from the newest) or as the
+    /// filesystem returns them
+    #[arg(long, value_enum, default_value = "name")]
+    input_glob_sort: GlobSort,
+
+    /// Only fetch URLs of this host, can be given multiple times; by default any host is allowed
+    #[arg(long, value_name = "HOST")]
+    allow_host: Vec<String>,
+
+    /// Also fetch URLs of hosts with a private, loopback, link-local, multicast or reserved IP
+    /// address, which are refused by default as the list of feeds might not be trusted. Without
+    /// it, hosts behind a proxy which resolves their names itself are refused as well
+    #[arg(long)]
+    allow_private: bool,
+}
+
+/// Options of the validate and dump subcommands
+#[derive(Args, Debug)]
+struct InspectOpt {
+    #[command(flatten)]
+    read: ReadOpt,
+
+    /// Read additional files or URLs from this file, one per line; blank lines and lines
+    /// starting with # are ignored
+    #[arg(long, value_parser)]
+    feeds_from: Option<PathBuf>,
 
-    /// Additional files
-    #[arg(value_parser, required = true)]
+    /// RSS files or http(s) URLs; glob patterns like feeds/*.xml are expanded, as are the *.xml
+    /// and *.rss files in directories and zip archives. Append :auto, :rss or :atom to override
+    /// --input-format for a single file
+    #[arg(value_parser, required_unless_present = "feeds_from")]
     files: Vec<PathBuf>,
+}
+
+/// Format of an input file
+#[derive(ValueEnum, serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+enum InputFormat {
+    /// Try RSS first, then Atom
+    Auto,
+    Rss,
+    Atom,
+}
+
+/// Order of the files expanded from glob patterns and directories
+#[derive(ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum GlobSort {
+    Name,
+    Mtime,
+    None,
+}
+
+/// Format of the merged file
+#[derive(ValueEnum, serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    Rss,
+    Atom,
+    /// JSON Feed 1.1
+    Json,
+}
+
+/// What to do with a merged RSS without entries
+#[derive(ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum OnEmpty {
+    Fail,
+    Keep,
+    Write,
+}
 
+/// Options read from a `--config` file
+///
+/// The keys are the same as the long command line options, using _ instead of -. Relative paths
+/// are relative to the current directory, same as on the command line.
+#[derive(serde::Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    input: Option<PathBuf>,
+    files: Option<Vec<PathBuf>>,
+    feeds_from: Option<PathBuf>,
+    output: Option<PathBuf>,
+    stdout: Option<bool>,
+    max_entries: Option<usize>,
+    keep_percent: Option<u8>,
+    min_entries: Option<usize>,
+    on_empty: Option<OnEmpty>,
+    max_bytes: Option<usize>,
+    warn_size: Option<usize>,
+    limit_per_source: Option<usize>,
+    fair_truncate: Option<bool>,
+    verbose: Option<bool>,
+    quiet: Option<bool>,
+    timeout: Option<u64>,
+    retries: Option<u32>,
+    max_redirects: Option<u32>,
+    input_format: Option<InputFormat>,
+    input_glob_sort: Option<GlobSort>,
+    output_format: Option<OutputFormat>,
+    dedup_key: Option<DedupKey>,
+    keep_main_metadata: Option<bool>,
+    primary: Option<Primary>,
+    image_from: Option<ImageFrom>,
+    insert: Option<Insert>,
+    ttl: Option<String>,
+    filter_cmd: Option<String>,
+    split_by_category: Option<PathBuf>,
+    only_new: Option<PathBuf>,
+    checksum: Option<PathBuf>,
+    dedup_report: Option<bool>,
+    channel_template: Option<PathBuf>,
+    since: Option<String>,
+    max_age: Option<String>,
+    drop_undated: Option<bool>,
+    expiry_element: Option<String>,
+    append_only: Option<bool>,
+    include_categories: Option<Vec<String>>,
+    exclude_categories: Option<Vec<String>>,
+    category_case_insensitive: Option<bool>,
+    title_match: Option<String>,
+    title_reject: Option<String>,
+    prefix_title: Option<String>,
+    prefix_all: Option<bool>,
+    seen_db: Option<PathBuf>,
+    stats_json: Option<PathBuf>,
+    seen_db_max: Option<usize>,
+    strip_html: Option<bool>,
+    max_description_chars: Option<usize>,
+    absolute_links: Option<bool>,
+    base_url: Option<String>,
+    default_author: Option<String>,
+    merge_comments: Option<bool>,
+    merge_update: Option<bool>,
+    add_source: Option<bool>,
+    compact: Option<bool>,
+    pretty: Option<bool>,
+    crlf: Option<bool>,
+    indent: Option<usize>,
+    no_update_builddate: Option<bool>,
+    no_verify: Option<bool>,
+    no_follow_symlinks: Option<bool>,
+    recursive: Option<bool>,
+    http_cache: Option<PathBuf>,
+    allow_host: Option<Vec<String>>,
+    allow_private: Option<bool>,
+    strict: Option<bool>,
+    no_progress: Option<bool>,
+    jobs: Option<usize>,
+    exit_code_on_change: Option<bool>,
+    normalize_guid: Option<bool>,
+    dedup_ignore_case: Option<bool>,
+    dedup_by_content: Option<bool>,
+    dedup_by_enclosure: Option<bool>,
+    dedup_by_media_url: Option<bool>,
+    title_dedup: Option<bool>,
+    keep_guidless: Option<bool>,
+    no_sort: Option<bool>,
+    reverse: Option<bool>,
+    /// Seed for --shuffle
+    shuffle: Option<u64>,
 }
 
-fn run_app() -> Result<(), ()> {
-    let opt = Opt::parse();
+/// Fill in the options not given on the command line from the `--config` file
+fn apply_config(opt: &mut Opt, matches: &ArgMatches, path: &Path) -> Result<(), AppError> {
+    let data = std::fs::read_to_string(path)
+        .map_err(|error| AppError::Io(format!("Cannot read config file {}", path.display()), error))?;
+    let config: Config = toml::from_str(&data)
+        .map_err(|error| AppError::Toml(format!("Invalid config file {}", path.display()), error))?;
+    let invalid = |error| AppError::Config(format!("Invalid config file {}: {}", path.display(), error));
+
+    // Options which cannot be used together, see conflicts_with in Opt. A config value which
+    // conflicts with an option on the command line is left out, so the command line wins.
+    const CONFLICTS: &[(&str, &str)] = &[
+        ("stdout", "output"),
+        ("compact", "pretty"),
+        ("compact", "indent"),
+        ("keep_main_metadata", "primary"),
+        ("keep_main_metadata", "channel_template"),
+        ("primary", "channel_template"),
+        ("append_only", "max_entries"),
+        ("append_only", "max_bytes"),
+        ("keep_percent", "max_entries"),
+        ("keep_percent", "append_only"),
+        ("shuffle", "no_sort"),
+        ("quiet", "verbose"),
+    ];
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let from_cli = |id: &str| {
+        given(id) || CONFLICTS.iter().any(|&(a, b)| (a == id && given(b)) || (b == id && given(a)))
+    };
+
+    // Conflicting options within the config file itself are an error, as on the command line
+    let table: toml::Table = toml::from_str(&data)
+        .map_err(|error| AppError::Toml(format!("Invalid config file {}", path.display()), error))?;
+    let in_config = |id: &str| table.get(id).is_some_and(|value| value.as_bool() != Some(false));
+    if let Some((a, b)) = CONFLICTS.iter().find(|&&(a, b)| in_config(a) && in_config(b)) {
+        return Err(invalid(format!("{} and {} cannot be used together", a, b)));
+    }
 
-    use std::fs::File;
-    use std::io::BufReader;
-    use rss::Channel;
-    use std::collections::HashSet;
+    // Options stored as is in Opt, or in the given part of it
+    macro_rules! merge {
+        ($target:expr; $($field:ident),*) => {
+            $(
+                if let Some(value) = config.$field {
+                    if !from_cli(stringify!($field)) {
+                        $target.$field = value;
+                    }
+                }
+            )*
+        };
+        ($($field:ident),*) => {
+            merge!(opt; $($field),*)
+        };
+    }
+    // Options which are optional in Opt as well
+    macro_rules! merge_some {
+        ($target:expr; $($field:ident),*) => {
+            $(
+                if config.$field.is_some() && !from_cli(stringify!($field)) {
+                    $target.$field = config.$field;
+                }
+            )*
+        };
+        ($($field:ident),*) => {
+            merge_some!(opt; $($field),*)
+        };
+    }
 
-    // Keep a list of known GUIDs to prevent duplicate RSS entries
-    let mut known_guids = HashSet::new();
+    merge!(files, stdout, max_entries, min_entries, on_empty, max_bytes, limit_per_source,
+           fair_truncate, output_format, primary, keep_main_metadata, image_from, insert,
+           drop_undated, append_only, include_categories, exclude_categories,
+           category_case_insensitive, prefix_all, seen_db_max, strip_html, absolute_links, compact,
+           pretty, crlf, indent, no_update_builddate, no_verify, no_follow_symlinks, no_progress,
+           jobs, exit_code_on_change, normalize_guid, dedup_ignore_case, dedup_by_content,
+           dedup_by_enclosure, dedup_by_media_url, title_dedup, keep_guidless, merge_comments,
+           merge_update, add_source, dedup_report, no_sort, reverse);
+    merge!(opt.read; verbose, quiet, timeout, retries, max_redirects, input_format, input_glob_sort,
+           strict, recursive, allow_host, allow_private);
+    merge_some!(opt.read; http_cache);
+    merge_some!(input, feeds_from, output, split_by_category, only_new, checksum, dedup_key,
+                channel_template, prefix_title, filter_cmd, seen_db, stats_json, default_author,
+                max_description_chars, keep_percent, warn_size);
 
-    if opt.verbose {
-        println!("Reading original RSS: {}", &opt.input.display())
+    if let Some(since) = config.since.filter(|_| !from_cli("since")) {
+        opt.since = Some(parse_since(&since).map_err(invalid)?);
+    }
+    if let Some(max_age) = config.max_age.filter(|_| !from_cli("max_age")) {
+        opt.max_age = Some(parse_duration(&max_age).map_err(invalid)?);
+    }
+    if let Some(ttl) = config.ttl.filter(|_| !from_cli("ttl")) {
+        opt.ttl = Some(parse_ttl(&ttl).map_err(invalid)?);
+    }
+    if let Some(element) = config.expiry_element.filter(|_| !from_cli("expiry_element")) {
+        opt.expiry_element = Some(parse_element(&element).map_err(invalid)?);
+    }
+    for (id, value, field) in [("title_match", config.title_match, &mut opt.title_match),
+                               ("title_reject", config.title_reject, &mut opt.title_reject)] {
+        if let Some(value) = value.filter(|_| !from_cli(id)) {
+            *field = Some(regex::Regex::new(&value).map_err(|error| invalid(error.to_string()))?);
+        }
+    }
+    if let Some(seed) = config.shuffle.filter(|_| !from_cli("shuffle")) {
+        opt.shuffle = Some(Some(seed));
     }
+    if let Some(base_url) = config.base_url.filter(|_| !from_cli("base_url")) {
+        opt.base_url = Some(base_url.parse().map_err(|error: url::ParseError| invalid(error.to_string()))?);
+    }
+
+    Ok(())
+}
 
-    let file = File::open(&opt.input).expect("Cannot read main RSS file");
-    let mut channel = Channel::read_from(BufReader::new(file)).expect("Cannot read main RSS file");
+/// Print the number of entries per file and the number of unique entries for --count-only
+///
+/// Entries are unique by their dedup key, entries without a key are all counted.
+fn print_counts(main: &Channel, main_name: &str, extras: &[Channel], files: &[PathBuf], options: &CombineOptions) {
+    let mut keys = std::collections::HashSet::new();
+    let mut unique = 0;
 
-    // Keep track of the number of RSS entries without a GUID, this to warn the user as the GUID is
-    // used to merge the entries
-    //
-    // It can indicate a problem with the RSS feed
-    let mut nr_missing_guids = 0;
+    let names = std::iter::once(main_name.to_string()).chain(files.iter().map(|file| file.display().to_string()));
+    for (name, channel) in names.zip(std::iter::once(main).chain(extras)) {
+        println!("{}: {} entries", name, channel.items().len());
+        for item in channel.items() {
+            match dedup_key(item, options.dedup_key) {
+                Some(key) if !keys.insert(options.compare_key(key)) => {}
+                _ => unique += 1,
+            }
+        }
+    }
+    println!("Total: {} unique entries", unique);
+}
+
+/// Print a summary of the merge to stderr
+fn print_stats(stats: &Stats, files: &[PathBuf]) {
+    eprintln!("Entries in main RSS: {}", stats.main_items);
+    for (filename, nr_items) in files.iter().zip(&stats.file_items) {
+        eprintln!("Entries in {}: {}", filename.display(), nr_items);
+    }
+    eprintln!("New entries merged: {}", stats.new_items);
+    eprintln!("Entries updated by a newer copy: {}", stats.updated_items);
+    eprintln!("Duplicate entries skipped: {}", stats.duplicates);
+    eprintln!("Entries skipped as seen before: {}", stats.seen_before);
+    eprintln!("Entries skipped due to category: {}", stats.filtered_category);
+    eprintln!("Entries skipped due to title: {}", stats.filtered_title);
+    eprintln!("Entries skipped due to limit per source: {}", stats.dropped_per_source);
+    eprintln!("Entries dropped as too old: {}", stats.dropped_old);
+    eprintln!("Entries dropped as expired: {}", stats.dropped_expired);
+    eprintln!("Entries of the main RSS dropped due to title: {}", stats.dropped_title);
+    eprintln!("Entries dropped due to maximum: {}", stats.dropped_max_entries);
+    eprintln!("Entries ignored without GUID: {}", stats.missing_guids);
+}
+
+/// Statistics written by --stats-json
+#[derive(serde::Serialize)]
+struct JsonStats {
+    started: String,
+    finished: String,
+    main: JsonFileStats,
+    files: Vec<JsonFileStats>,
+    new_items: usize,
+    updated_items: usize,
+    duplicates: usize,
+    seen_before: usize,
+    filtered_category: usize,
+    filtered_title: usize,
+    dropped_per_source: usize,
+    dropped_old: usize,
+    dropped_expired: usize,
+    dropped_title: usize,
+    dropped_max_entries: usize,
+    missing_guids: usize,
+    items: usize,
+}
+
+/// Number of entries in an input file for --stats-json
+#[derive(serde::Serialize)]
+struct JsonFileStats {
+    name: String,
+    items: usize,
+}
+
+/// Write the statistics as JSON to a file, or stdout for -
+fn write_stats_json(path: &Path, stats: &Stats, main_name: &str, files: &[PathBuf], nr_items: usize,
+                    started: chrono::DateTime<chrono::Utc>) -> Result<(), AppError> {
+    let json = JsonStats {
+        started: started.to_rfc3339(),
+        finished: now().to_rfc3339(),
+        main: JsonFileStats { name: main_name.to_string(), items: stats.main_items },
+        files: files.iter().zip(&stats.file_items)
+            .map(|(filename, &items)| JsonFileStats { name: filename.display().to_string(), items })
+            .collect(),
+        new_items: stats.new_items,
+        updated_items: stats.updated_items,
+        duplicates: stats.duplicates,
+        seen_before: stats.seen_before,
+        filtered_category: stats.filtered_category,
+        filtered_title: stats.filtered_title,
+        dropped_per_source: stats.dropped_per_source,
+        dropped_old: stats.dropped_old,
+        dropped_expired: stats.dropped_expired,
+        dropped_title: stats.dropped_title,
+        dropped_max_entries: stats.dropped_max_entries,
+        missing_guids: stats.missing_guids,
+        items: nr_items,
+    };
+
+    let msg = || format!("Cannot write statistics to {}", path.display());
+    let mut data = serde_json::to_vec_pretty(&json).map_err(|error| AppError::Json(msg(), error))?;
+    data.push(b'\n');
+    if path == Path::new("-") {
+        io::stdout().lock().write_all(&data)
+    } else {
+        std::fs::write(path, data)
+    }.map_err(|error| AppError::Io(msg(), error))
+}
+
+/// Log to stderr, by default only the warnings and the line summarizing the merge
+///
+/// --verbose and --quiet set the level of rss-combine itself, RUST_LOG takes precedence over both.
+/// Messages are printed without any decoration other than WARNING: or error:, so that the output is
+/// stable for scripts.
+fn init_logger(opt: &ReadOpt) {
+    let level = if opt.verbose {
+        log::LevelFilter::Debug
+    } else if opt.quiet {
+        log::LevelFilter::Error
+    } else {
+        log::LevelFilter::Info
+    };
+
+    env_logger::Builder::new()
+        .filter_level(log::LevelFilter::Warn)
+        .filter_module("rss_combine", level)
+        .parse_default_env()
+        .format(|buf, record| match record.level() {
+            log::Level::Error => writeln!(buf, "error: {}", record.args()),
+            log::Level::Warn => writeln!(buf, "WARNING: {}", record.args()),
+            _ => writeln!(buf, "{}", record.args()),
+        })
+        .init();
+}
 
-    // Two lists:
-    // a) list of original RSS entries
-    // b) list of new RSS entries
-    let mut items_orig = channel.items_mut().to_vec();
-    let mut items_extra = Vec::new();
+/// Log a warning about a problem with the input, or return it as error under --strict
+macro_rules! problem {
+    ($opt:expr, $($arg:tt)*) => {
+        if $opt.strict {
+            return Err(AppError::Strict(format!($($arg)*)));
+        }
+        log::warn!($($arg)*);
+    };
+}
+
+/// Exit status of rss-combine, see [`EXIT_STATUS_HELP`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ExitCode {
+    /// Everything went fine, also when nothing changed
+    Success = 0,
+    /// A fatal error, e.g. the main RSS could not be read or the merged RSS not written
+    Error = 1,
+    /// Invalid command line options or config file
+    Usage = 2,
+    /// New entries were merged, only with --exit-code-on-change
+    Changed = 10,
+    /// A problem with the input made fatal by --strict, or files failing validation
+    Invalid = 20,
+}
+
+/// The exit statuses of [`ExitCode`] as shown by --help
+const EXIT_STATUS_HELP: &str = "Exit status:
+  0   Success, also when nothing changed
+  1   Fatal error, e.g. the main RSS could not be read or the merged RSS not written
+  2   Invalid command line options or config file
+  10  New entries were merged, only with --exit-code-on-change
+  20  Problem with the input made fatal by --strict, or files failing validation";
+
+impl From<&AppError> for ExitCode {
+    fn from(error: &AppError) -> Self {
+        match error {
+            AppError::Config(_) | AppError::Toml(..) => ExitCode::Usage,
+            AppError::Strict(_) => ExitCode::Invalid,
+            _ => ExitCode::Error,
+        }
+    }
+}
+
+/// Errors which abort the merge
+///
+/// Each variant carries a message describing what was being done when the error happened
+#[derive(Debug)]
+enum AppError {
+    /// Reading or writing a file failed
+    Io(String, io::Error),
+    /// Fetching a RSS file over HTTP(S) failed
+    Http(String, Box<ureq::Error>),
+    /// Parsing or serializing a RSS file failed
+    Rss(String, rss::Error),
+    /// Parsing an Atom file failed
+    Atom(String, feed_rs::parser::ParseFeedError),
+    /// Serializing an Atom file failed
+    AtomWrite(String, atom_syndication::Error),
+    /// Serializing a JSON Feed failed
+    Json(String, serde_json::Error),
+    /// Parsing the config file failed
+    Toml(String, toml::de::Error),
+    /// The given options cannot be used together
+    Config(String),
+    /// A problem with the input which is fatal with --strict
+    Strict(String),
+    /// Fetching the URL is not allowed by --allow-host or --allow-private
+    Blocked(String),
+    /// The merged RSS has no entries, with --on-empty fail
+    Empty(String),
+}
 
-    for item in items_orig.iter() {
-        // This logic will remove any RSS items without an GUID
-        if let Some(guid) = item.guid() {
-            known_guids.insert(guid.value().to_string());
-        } else {
-            nr_missing_guids += 1;
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppError::Io(msg, error) => write!(f, "{}: {}", msg, error),
+            AppError::Http(msg, error) => write!(f, "{}: {}", msg, error),
+            AppError::Rss(msg, error) => write!(f, "{}: {}", msg, error),
+            AppError::Atom(msg, error) => write!(f, "{}: {}", msg, error),
+            AppError::AtomWrite(msg, error) => write!(f, "{}: {}", msg, error),
+            AppError::Json(msg, error) => write!(f, "{}: {}", msg, error),
+            AppError::Toml(msg, error) => write!(f, "{}: {}", msg, error),
+            AppError::Config(msg) => write!(f, "{}", msg),
+            AppError::Strict(msg) => write!(f, "{}", msg),
+            AppError::Blocked(msg) => write!(f, "{}", msg),
+            AppError::Empty(msg) => write!(f, "{}", msg),
         }
     }
+}
 
-    for rss_filename in opt.files {
-        if opt.verbose {
-            println!("Reading additional RSS: {}", rss_filename.display());
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Io(_, error) => Some(error),
+            AppError::Http(_, error) => Some(error.as_ref()),
+            AppError::Rss(_, error) => Some(error),
+            AppError::Atom(_, error) => Some(error),
+            AppError::AtomWrite(_, error) => Some(error),
+            AppError::Json(_, error) => Some(error),
+            AppError::Toml(_, error) => Some(error),
+            AppError::Config(_) | AppError::Strict(_) | AppError::Blocked(_) | AppError::Empty(_) => None,
         }
-        let file2 = match File::open(&rss_filename) {
-            Ok(file2) => file2,
-            Err(error) => {
-                eprintln!("WARNING: Skipping unreadable RSS file {}: {}", rss_filename.display(), error);
+    }
+}
+
+/// Parse a duration in hours, days or weeks, e.g. 7d
+fn parse_duration(value: &str) -> Result<chrono::Duration, String> {
+    let invalid = || format!("'{}' is not a duration like 7d", value);
+    let split = value.len().checked_sub(1).filter(|&split| value.is_char_boundary(split)).ok_or_else(invalid)?;
+    let (number, unit) = value.split_at(split);
+    // Only digits, as a negative duration would move the cutoff into the future
+    if number.is_empty() || !number.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let number: i64 = number.parse().map_err(|_| invalid())?;
+    match unit {
+        "h" => chrono::Duration::try_hours(number),
+        "d" => chrono::Duration::try_days(number),
+        "w" => chrono::Duration::try_weeks(number),
+        _ => None,
+    }.ok_or_else(invalid)
+}
+
+/// Parse --ttl, either auto, keep or a number of minutes
+fn parse_ttl(value: &str) -> Result<Ttl, String> {
+    match value {
+        "auto" => Ok(Ttl::Auto),
+        "keep" => Ok(Ttl::Keep),
+        _ => value.parse().map(Ttl::Minutes)
+            .map_err(|_| format!("'{}' is neither auto, keep nor a number of minutes", value)),
+    }
+}
+
+/// Parse an extension element like x:expires into its prefix and name
+fn parse_element(value: &str) -> Result<(String, String), String> {
+    match value.split_once(':') {
+        Some((prefix, name)) if !prefix.is_empty() && !name.is_empty() && !name.contains(':') => {
+            Ok((prefix.to_string(), name.to_string()))
+        }
+        _ => Err(format!("'{}' is not an element like x:expires", value)),
+    }
+}
+
+/// The --now override of the current time, set once before merging
+static NOW: std::sync::OnceLock<chrono::DateTime<chrono::Utc>> = std::sync::OnceLock::new();
+
+/// The current time, unless overridden by --now
+fn now() -> chrono::DateTime<chrono::Utc> {
+    NOW.get().copied().unwrap_or_else(chrono::Utc::now)
+}
+
+/// The --since cutoff
+///
+/// A relative cutoff is only resolved after parsing the options, as --now could be given after it
+#[derive(Clone, Copy, Debug)]
+enum Since {
+    Date(chrono::DateTime<chrono::FixedOffset>),
+    Ago(chrono::Duration),
+}
+
+impl Since {
+    fn resolve(self) -> chrono::DateTime<chrono::FixedOffset> {
+        match self {
+            Since::Date(date) => date,
+            Since::Ago(duration) => (now() - duration).fixed_offset(),
+        }
+    }
+}
+
+/// Parse the --since cutoff, either a RFC 3339 date or a duration before now
+fn parse_since(value: &str) -> Result<Since, String> {
+    if let Ok(date) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(Since::Date(date));
+    }
+
+    let duration = parse_duration(value)
+        .map_err(|_| format!("'{}' is neither a RFC 3339 date nor a duration like 7d", value))?;
+    Ok(Since::Ago(duration))
+}
+
+/// Check if a RSS file should be fetched over HTTP(S)
+fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .map(|path| path.starts_with("http://") || path.starts_with("https://"))
+        .unwrap_or(false)
+}
+
+/// Whether two files or URLs refer to the same RSS file
+///
+/// Files are compared by their canonical path, URLs after normalizing them.
+fn same_source(a: &Path, b: &Path) -> bool {
+    if is_url(a) || is_url(b) {
+        let normalize = |path: &Path| {
+            let url = path.to_string_lossy();
+            url::Url::parse(&url).map(|url| url.to_string()).unwrap_or_else(|_| url.into_owned())
+        };
+        return is_url(a) && is_url(b) && normalize(a) == normalize(b);
+    }
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Read the list of additional files given by --feeds-from
+fn read_feed_list(path: &Path) -> Result<Vec<PathBuf>, AppError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|error| AppError::Io(format!("Cannot read feed list {}", path.display()), error))?;
+
+    let files: Vec<PathBuf> = contents.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect();
+
+    if files.is_empty() {
+        return Err(AppError::Config(format!("Feed list {} does not contain any RSS files", path.display())));
+    }
+
+    Ok(files)
+}
+
+/// Split an optional format suffix from an additional file, e.g. feed.xml:atom
+///
+/// Only a suffix after the last colon which is a known input format is split off, so Windows
+/// drive letters and URLs are kept intact.
+fn split_format(file: &Path) -> (PathBuf, Option<InputFormat>) {
+    if let Some((path, suffix)) = file.to_str().and_then(|file| file.rsplit_once(':')) {
+        if let Ok(format) = InputFormat::from_str(suffix, false) {
+            return (PathBuf::from(path), Some(format));
+        }
+    }
+
+    (file.to_path_buf(), None)
+}
+
+/// Combine the files given on the command line with those of --feeds-from and expand them
+fn collect_files(files: &[PathBuf], feeds_from: Option<&Path>, opt: &ReadOpt) -> Result<Vec<(PathBuf, InputFormat)>, AppError> {
+    let mut files = files.to_vec();
+    if let Some(feeds_from) = feeds_from {
+        files.extend(read_feed_list(feeds_from)?);
+    }
+    expand_files(&files, opt)
+}
+
+/// Find the *.xml and *.rss files within a directory, in the order of the filesystem
+///
+/// Hidden files and directories are skipped.
+fn read_dir_files(dir: &Path, recursive: bool) -> io::Result<Vec<PathBuf>> {
+    let entries = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut files = Vec::new();
+    for path in entries {
+        if path.file_name().and_then(|name| name.to_str()).is_none_or(|name| name.starts_with('.')) {
+            continue
+        }
+        if path.is_dir() {
+            if recursive {
+                files.extend(read_dir_files(&path, recursive)?);
+            }
+        } else if path.extension().is_some_and(|extension| extension == "xml" || extension == "rss") {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Put the files expanded from a glob pattern or directory in the order of --input-glob-sort
+///
+/// Files of which the modification time cannot be read go last.
+fn sort_files(files: &mut [PathBuf], sort: GlobSort) {
+    match sort {
+        GlobSort::Name => files.sort(),
+        GlobSort::Mtime => files.sort_by_cached_key(|file| {
+            let modified = std::fs::metadata(file).and_then(|metadata| metadata.modified()).ok();
+            (modified.is_none(), modified, file.clone())
+        }),
+        GlobSort::None => (),
+    }
+}
+
+/// Expand any glob patterns and directories within the additional files, keeping their format
+///
+/// Other paths and URLs are used as is
+fn expand_files(files: &[PathBuf], opt: &ReadOpt) -> Result<Vec<(PathBuf, InputFormat)>, AppError> {
+    let mut expanded = Vec::new();
+
+    for file in files {
+        let (file, format) = split_format(file);
+        let format = format.unwrap_or(opt.input_format);
+
+        if !is_url(&file) && file.to_str().is_some_and(|file| file.contains("://")) {
+            problem!(opt, "Skipping {}: only http and https URLs can be fetched", file.display());
+            continue
+        }
+
+        if !is_url(&file) && file.is_dir() {
+            match read_dir_files(&file, opt.recursive) {
+                Ok(paths) if paths.is_empty() => {
+                    problem!(opt, "Skipping directory {} without any RSS files", file.display());
+                }
+                Ok(mut paths) => {
+                    sort_files(&mut paths, opt.input_glob_sort);
+                    expanded.extend(paths.into_iter().map(|path| (path, format)));
+                }
+                Err(error) => {
+                    problem!(opt, "Skipping unreadable directory {}: {}", file.display(), error);
+                }
+            }
+            continue
+        }
+
+        if !is_url(&file) && file.extension().is_some_and(|extension| extension == "zip") && file.is_file() {
+            match zip_entries(&file) {
+                Ok(entries) if entries.is_empty() => {
+                    problem!(opt, "Skipping archive {} without any RSS files", file.display());
+                }
+                Ok(entries) => expanded.extend(entries.into_iter().map(|entry| (file.join(entry), format))),
+                Err(error) => {
+                    problem!(opt, "Skipping unreadable archive {}: {}", file.display(), error);
+                }
+            }
+            continue
+        }
+
+        let pattern = match file.to_str() {
+            Some(pattern) if !is_url(&file) && pattern.contains(['*', '?', '[']) => pattern,
+            _ => {
+                expanded.push((file, format));
                 continue
             }
         };
-        // The channel variable is reused so that the merged RSS contains the fields from the
-        // newest RSS file
-        channel = match Channel::read_from(BufReader::new(file2)) {
-            Ok(channel) => channel,
+
+        let paths = match glob::glob(pattern) {
+            Ok(paths) => paths,
             Err(error) => {
-                eprintln!("WARNING: Skipping unparseble RSS file {}: {}", rss_filename.display(), error);
+                problem!(opt, "Skipping invalid pattern {}: {}", pattern, error);
                 continue
             }
         };
 
-        /*
-        // Update last build date
-        // Obsolete due to reuse of channel variable
-        if let Some(date) = addchannel.last_build_date() {
-            channel.set_last_build_date(date.to_string());
-        } */
-
-        let mut vec_items = channel.items_mut().to_vec();
-
-        let mut i = 0;
-        while i != vec_items.len() {
-            let guid = match vec_items[i].guid() {
-                Some(guid) => guid,
-                None       => {
-                    nr_missing_guids += 1;
-                    i +=1;
-                    continue;
+        let mut matches = Vec::new();
+        for path in paths {
+            match path {
+                Ok(path) => matches.push(path),
+                Err(error) => {
+                    problem!(opt, "Skipping unreadable RSS file {}: {}", error.path().display(), error.error());
                 }
-            };
-
-            if known_guids.contains(guid.value()) {
-                i += 1;
-                continue
             }
+        }
 
-            known_guids.insert(guid.value().to_string());
-            items_extra.push(vec_items.remove(i));
+        if matches.is_empty() {
+            problem!(opt, "Skipping pattern {} without any matching RSS files", pattern);
         }
+        sort_files(&mut matches, opt.input_glob_sort);
+        expanded.extend(matches.into_iter().map(|path| (path, format)));
     }
 
-    // Mention anything weird in the data
-    if nr_missing_guids > 0 {
-        eprintln!("WARNING: Ignored {} RSS entres without a GUID", nr_missing_guids);
-    }
+    Ok(expanded)
+}
 
-    // We only rewrite the RSS in case there are additional entires
-    //
-    // Updates of any other field is not important
-    if items_extra.len() == 0 {
-       if opt.verbose {
-           println!("No changes made");
-        }
-        return Ok(())
+/// Find the *.xml and *.rss files within a zip archive, sorted by name
+///
+/// Hidden files and directories are skipped, like within a directory.
+fn zip_entries(archive: &Path) -> io::Result<Vec<String>> {
+    let zip = zip::ZipArchive::new(File::open(archive)?).map_err(io::Error::other)?;
+    let mut entries = zip.file_names()
+        .map(|name| name.map(String::from).map_err(io::Error::other))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.retain(|name| name.split('/').all(|part| !part.starts_with('.') && part != "__MACOSX")
+        && (name.ends_with(".xml") || name.ends_with(".rss")));
+    entries.sort();
+    Ok(entries)
+}
+
+/// Split a path like feeds.zip/a.xml into the zip archive and the name of the entry within it
+fn split_zip_path(path: &Path) -> Option<(&Path, String)> {
+    if path.exists() {
+        return None;
     }
+    let archive = path.ancestors().skip(1)
+        .find(|archive| archive.extension().is_some_and(|extension| extension == "zip") && archive.is_file())?;
+    let entry = path.strip_prefix(archive).ok()?.iter()
+        .map(|part| part.to_str())
+        .collect::<Option<Vec<_>>>()?
+        .join("/");
+    Some((archive, entry))
+}
 
-    // Combine all entries into items_extra
-    items_extra.append(&mut items_orig); // this clears items_orig
+/// Read an entry of a zip archive into memory
+fn read_zip_entry(archive: &Path, entry: &str) -> io::Result<Vec<u8>> {
+    let mut zip = zip::ZipArchive::new(File::open(archive)?).map_err(io::Error::other)?;
+    let mut data = Vec::new();
+    zip.by_name(entry).map_err(io::Error::other)?.read_to_end(&mut data)?;
+    Ok(data)
+}
 
-    // The number of entries is only limited in case entries are merged
-    if opt.max_entries > 0 && items_extra.len() > opt.max_entries  {
-        if opt.verbose {
-            println!("Restricting RSS size to newest {} entries", opt.max_entries);
-        }
-        items_extra.truncate(opt.max_entries);
+/// Transparently decompress gzip compressed RSS files, recognized by their magic number
+fn decompress<'a>(mut reader: Box<dyn BufRead + 'a>) -> io::Result<Box<dyn BufRead + 'a>> {
+    if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(BufReader::new(flate2::bufread::GzDecoder::new(reader))))
+    } else {
+        Ok(reader)
     }
+}
 
-    // Add the entries back to the RSS feed
-    channel.set_items(items_extra);
+/// What is needed for fetching URLs
+struct Http {
+    agent: ureq::Agent,
+    cache: Option<HttpCache>,
+}
 
-    // And write the new file
-    let mut outfile = tempfile_fast::Sponge::new_for(&opt.input).unwrap();
-    channel.pretty_write_to(&mut outfile, b' ', 2).unwrap(); // // write to the channel to a writer
-    outfile.commit().expect("Cannot store merged RSS back into main RSS file");
+/// Create the HTTP agent used for fetching all URLs, together with the --http-cache
+fn build_http(opt: &ReadOpt) -> Result<Http, AppError> {
+    // Redirects are followed by fetch_allowed, so that each URL is checked
+    let config = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(opt.timeout)))
+        .max_redirects(0)
+        .build();
+    let agent = if opt.allow_private {
+        config.into()
+    } else {
+        ureq::Agent::with_parts(config, ureq::unversioned::transport::DefaultConnector::default(), PublicResolver)
+    };
+    let cache = opt.http_cache.as_deref().map(HttpCache::open).transpose()?;
+    Ok(Http { agent, cache })
+}
 
-    Ok(())
+/// A fetched URL in the index of the --http-cache
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+struct CachedFeed {
+    /// File within the cache directory with the body of the last response
+    file: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
 }
 
+/// The last response of each URL, so that they can be fetched conditionally
+///
+/// The directory contains index.json mapping each URL to a [`CachedFeed`], next to a file with
+/// the body of each URL.
+struct HttpCache {
+    dir: PathBuf,
+    index: std::sync::Mutex<std::collections::HashMap<String, CachedFeed>>,
+}
 
-fn main() {
-    std::process::exit(match run_app() {
-        Ok(_) => 0,
-        Err(err) => {
-            eprintln!("error: {:?}", err);
-            1
-        }
-    });
+impl HttpCache {
+    fn open(dir: &Path) -> Result<HttpCache, AppError> {
+        let msg = || format!("Cannot read HTTP cache {}", dir.display());
+        std::fs::create_dir_all(dir).map_err(|error| AppError::Io(msg(), error))?;
+        // A broken index only means everything is fetched again
+        let index = match std::fs::read(dir.join("index.json")) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|error| {
+                log::warn!("Ignoring invalid HTTP cache index in {}: {}", dir.display(), error);
+                Default::default()
+            }),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Default::default(),
+            Err(error) => return Err(AppError::Io(msg(), error)),
+        };
+        Ok(HttpCache { dir: dir.to_path_buf(), index: std::sync::Mutex::new(index) })
+    }
+
+    /// The cached response of an URL, if its body is still there
+    fn get(&self, url: &str) -> Option<(CachedFeed, Vec<u8>)> {
+        let feed = self.index.lock().expect("HTTP cache lock").get(url)?.clone();
+        let body = std::fs::read(self.dir.join(&feed.file)).ok()?;
+        Some((feed, body))
+    }
+
+    /// Remember the response of an URL; failing to do so is not fatal
+    fn store(&self, url: &str, etag: Option<String>, last_modified: Option<String>, body: &[u8]) {
+        let mut index = self.index.lock().expect("HTTP cache lock");
+        let number = index.len();
+        let file = index.get(url).map_or_else(|| format!("{}.feed", number), |feed| feed.file.clone());
+        index.insert(url.to_string(), CachedFeed { file: file.clone(), etag, last_modified });
+
+        let result = serde_json::to_vec_pretty(&*index).map_err(io::Error::other)
+            .and_then(|data| store_file(&self.dir.join(&file), body).map(|()| data))
+            .and_then(|data| store_file(&self.dir.join("index.json"), &data));
+        if let Err(error) = result {
+            log::warn!("Cannot update HTTP cache {}: {}", self.dir.display(), error);
+        }
+    }
+}
+
+/// Whether fetching an URL might succeed when trying again later
+///
+/// Client errors (4xx) are not expected to go away
+fn is_transient(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::StatusCode(status) => *status >= 500,
+        ureq::Error::Io(_) | ureq::Error::Timeout(_) | ureq::Error::ConnectionFailed => true,
+        _ => false,
+    }
+}
+
+/// Fetch an URL, retrying server and connection errors with an exponential backoff
+fn fetch(url: &str, agent: &ureq::Agent, headers: &[(&str, &str)], opt: &ReadOpt) -> Result<ureq::http::Response<ureq::Body>, ureq::Error> {
+    let mut attempt = 0;
+    loop {
+        let request = headers.iter().fold(agent.get(url), |request, (name, value)| request.header(*name, *value));
+        match request.call() {
+            Err(error) if attempt < opt.retries && is_transient(&error) => {
+                let delay = Duration::from_secs(1 << attempt.min(6));
+                attempt += 1;
+                log::debug!("Retrying {} in {}s ({}/{}): {}", url, delay.as_secs(), attempt, opt.retries, error);
+                std::thread::sleep(delay);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether an IP address is not reachable from the internet, e.g. 127.0.0.1 or 192.168.1.1
+///
+/// Besides private and local addresses this includes 0.0.0.0/8, shared address space
+/// (100.64.0.0/10), multicast and the reserved 240.0.0.0/4 including broadcast.
+fn is_private_ip(ip: std::net::IpAddr) -> bool {
+    match ip {
+        std::net::IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_multicast()
+                || octets[0] == 0 || octets[0] >= 240 || (octets[0] == 100 && octets[1] & 0xc0 == 64)
+        }
+        std::net::IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_private_ip(ip.into()),
+            None => ip.is_loopback() || ip.is_unspecified() || ip.is_multicast()
+                || ip.segments()[0] & 0xfe00 == 0xfc00 || ip.segments()[0] & 0xffc0 == 0xfe80,
+        },
+    }
+}
+
+/// A host resolving to a private address, refused by [`PublicResolver`]
+#[derive(Debug)]
+struct PrivateAddress(String);
+
+impl fmt::Display for PrivateAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for PrivateAddress {}
+
+/// Resolver refusing hosts with a private address, used unless --allow-private
+///
+/// Checking the addresses ureq connects to, instead of resolving the host separately beforehand,
+/// prevents a host from resolving to a public address for the check and a private one for the
+/// connection. The host of a proxy is not checked, as that was chosen by whoever runs this.
+#[derive(Debug)]
+struct PublicResolver;
+
+impl ureq::unversioned::resolver::Resolver for PublicResolver {
+    fn resolve(&self, uri: &ureq::http::Uri, config: &ureq::config::Config, timeout: ureq::unversioned::transport::NextTimeout)
+               -> Result<ureq::unversioned::resolver::ResolvedSocketAddrs, ureq::Error> {
+        let addresses = ureq::unversioned::resolver::DefaultResolver::default().resolve(uri, config, timeout)?;
+        if config.proxy().is_some_and(|proxy| uri.host() == Some(proxy.host())) {
+            return Ok(addresses);
+        }
+        match addresses.iter().find(|address| is_private_ip(address.ip())) {
+            Some(address) => Err(ureq::Error::Other(Box::new(PrivateAddress(format!(
+                "host {} has private address {}, use --allow-private to fetch it",
+                uri.host().unwrap_or_default(), address.ip()))))),
+            None => Ok(addresses),
+        }
+    }
+}
+
+/// Check an URL against --allow-host and --allow-private, returns why it is refused
+///
+/// The addresses of a host name are checked while connecting by [`PublicResolver`]. A proxy
+/// resolving the host itself would bypass that, so such URLs are refused.
+fn check_url(url: &str, proxy: Option<&ureq::Proxy>, opt: &ReadOpt) -> Result<(), String> {
+    let parsed = url::Url::parse(url).map_err(|error| format!("invalid URL {}: {}", url, error))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("only http and https URLs can be fetched, not {}", url));
+    }
+    let host = parsed.host().ok_or_else(|| format!("URL {} without a host", url))?;
+    let name = parsed.host_str().unwrap_or_default().trim_start_matches('[').trim_end_matches(']');
+    if !opt.allow_host.is_empty() && !opt.allow_host.iter().any(|allowed| allowed.eq_ignore_ascii_case(name)) {
+        return Err(format!("host {} is not allowed by --allow-host", name));
+    }
+    if opt.allow_private {
+        return Ok(());
+    }
+
+    let ip: std::net::IpAddr = match host {
+        url::Host::Ipv4(ip) => ip.into(),
+        url::Host::Ipv6(ip) => ip.into(),
+        url::Host::Domain(_) => {
+            let proxied = proxy.filter(|proxy| !proxy.resolve_target())
+                .filter(|proxy| url.parse().is_ok_and(|uri| !proxy.is_no_proxy(&uri)));
+            return match proxied {
+                Some(proxy) => Err(format!("the address of host {} cannot be checked as proxy {} resolves it, \
+                                            use --allow-private to fetch it", name, proxy.host())),
+                None => Ok(()),
+            };
+        }
+    };
+    if is_private_ip(ip) {
+        return Err(format!("host {} has private address {}, use --allow-private to fetch it", name, ip));
+    }
+    Ok(())
+}
+
+/// Fetch an URL, following redirects as long as they lead to allowed URLs
+fn fetch_allowed(url: &str, agent: &ureq::Agent, headers: &[(&str, &str)], opt: &ReadOpt) -> Result<ureq::http::Response<ureq::Body>, AppError> {
+    let msg = || format!("unreadable RSS file {}", url);
+    let blocked = |reason| AppError::Blocked(format!("blocked URL {}: {}", url, reason));
+
+    let mut current = url.to_string();
+    for _ in 0..=opt.max_redirects {
+        check_url(&current, agent.config().proxy(), opt).map_err(blocked)?;
+        let response = fetch(&current, agent, headers, opt).map_err(|error| match error {
+            ureq::Error::Other(error) if error.is::<PrivateAddress>() => blocked(error.to_string()),
+            error => AppError::Http(msg(), Box::new(error)),
+        })?;
+
+        let location = response.headers().get("location")
+            .filter(|_| response.status().is_redirection())
+            .and_then(|location| location.to_str().ok());
+        let Some(location) = location else {
+            return Ok(response);
+        };
+        let next = url::Url::parse(&current).and_then(|base| base.join(location))
+            .map_err(|error| blocked(format!("invalid redirect to {}: {}", location, error)))?
+            .to_string();
+        log::debug!("Redirected from {} to {}", current, next);
+        current = next;
+    }
+    Err(AppError::Http(msg(), Box::new(ureq::Error::TooManyRedirects)))
+}
+
+/// Call `read` for each of the files using `jobs` threads, or one per CPU for 0
+///
+/// The results are in the order of the files.
+fn read_parallel<T: Send>(files: &[(PathBuf, InputFormat)], jobs: usize, read: impl Fn(&Path, InputFormat) -> T + Sync) -> Vec<T> {
+    let jobs = match jobs {
+        0 => std::thread::available_parallelism().map_or(1, usize::from),
+        jobs => jobs,
+    };
+    if jobs <= 1 || files.len() <= 1 {
+        return files.iter().map(|(path, format)| read(path, *format)).collect();
+    }
+
+    // Each thread takes the next file which is not being read yet
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let mut results = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.min(files.len())).map(|_| scope.spawn(|| {
+            let mut results = Vec::new();
+            loop {
+                let index = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let Some((path, format)) = files.get(index) else { break };
+                results.push((index, read(path, *format)));
+            }
+            results
+        })).collect();
+        workers.into_iter()
+            .flat_map(|worker| worker.join().expect("reading thread panicked"))
+            .collect::<Vec<_>>()
+    });
+    results.sort_by_key(|&(index, _)| index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Open a RSS file for reading, fetching it first in case of an URL
+///
+/// Compressed HTTP responses (Content-Encoding: gzip) are decompressed by ureq itself. With
+/// --http-cache the URL is only fetched again if it changed according to the server.
+fn open_rss(path: &Path, http: &Http, opt: &ReadOpt) -> Result<Box<dyn BufRead>, AppError> {
+    let msg = || format!("unreadable RSS file {}", path.display());
+
+    let reader: Box<dyn BufRead> = if is_url(path) {
+        let url = path.to_string_lossy();
+        let cached = http.cache.as_ref().and_then(|cache| cache.get(&url));
+        let mut headers = Vec::new();
+        if let Some((feed, _)) = &cached {
+            headers.extend(feed.etag.as_deref().map(|etag| ("If-None-Match", etag)));
+            headers.extend(feed.last_modified.as_deref().map(|date| ("If-Modified-Since", date)));
+        }
+
+        let response = fetch_allowed(&url, &http.agent, &headers, opt)?;
+
+        match (&http.cache, cached) {
+            (_, Some((_, body))) if response.status() == ureq::http::StatusCode::NOT_MODIFIED => {
+                log::debug!("Not modified, using the cached copy: {}", url);
+                Box::new(io::Cursor::new(body))
+            }
+            (Some(cache), _) => {
+                let header = |name| response.headers().get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string);
+                let (etag, last_modified) = (header("etag"), header("last-modified"));
+
+                let mut body = Vec::new();
+                response.into_body().into_reader().read_to_end(&mut body)
+                    .map_err(|error| AppError::Io(msg(), error))?;
+                if etag.is_some() || last_modified.is_some() {
+                    cache.store(&url, etag, last_modified, &body);
+                }
+                Box::new(io::Cursor::new(body))
+            }
+            (None, _) => Box::new(BufReader::new(response.into_body().into_reader())),
+        }
+    } else if let Some((archive, entry)) = split_zip_path(path) {
+        let data = read_zip_entry(archive, &entry).map_err(|error| AppError::Io(msg(), error))?;
+        Box::new(io::Cursor::new(data))
+    } else {
+        let file = File::open(path).map_err(|error| AppError::Io(msg(), error))?;
+        Box::new(BufReader::new(file))
+    };
+
+    decompress(reader).map_err(|error| AppError::Io(msg(), error))
+}
+
+/// Convert an Atom entry into a RSS entry
+fn atom_entry_to_item(entry: feed_rs::model::Entry) -> Item {
+    let mut item = Item::default();
+    item.set_guid(Guid { value: entry.id, permalink: false });
+    item.set_title(entry.title.map(|title| title.content));
+    item.set_enclosure(entry.links.iter()
+        .find(|link| link.rel.as_deref() == Some("enclosure"))
+        .map(|link| rss::Enclosure {
+            url: link.href.clone(),
+            length: link.length.unwrap_or(0).to_string(),
+            mime_type: link.media_type.clone().unwrap_or_default(),
+        }));
+    item.set_link(atom_link(entry.links));
+    item.set_description(entry.summary.map(|summary| summary.content));
+    item.set_pub_date(entry.published.or(entry.updated).map(|date| date.to_rfc2822()));
+    // Atom authors are names, like <dc:creator>
+    let creators: Vec<_> = entry.authors.into_iter().filter_map(|author| author.name).collect();
+    if !creators.is_empty() {
+        item.set_dublin_core_ext(rss::extension::dublincore::DublinCoreExtension {
+            creators,
+            ..Default::default()
+        });
+    }
+    item
+}
+
+/// Select the link to the website, preferring the alternate link over e.g. the self link
+fn atom_link(links: Vec<feed_rs::model::Link>) -> Option<String> {
+    let position = links.iter()
+        .position(|link| link.rel.as_deref().unwrap_or("alternate") == "alternate")
+        .unwrap_or(0);
+    links.into_iter().nth(position).map(|link| link.href)
+}
+
+/// Parse an Atom file, converting it into a RSS channel
+fn parse_atom<R: Read>(reader: R) -> Result<Channel, feed_rs::parser::ParseFeedError> {
+    let feed = feed_rs::parser::parse(reader)?;
+
+    let mut channel = Channel::default();
+    channel.set_title(feed.title.map(|title| title.content).unwrap_or_default());
+    channel.set_link(atom_link(feed.links).unwrap_or_default());
+    channel.set_description(feed.description.map(|description| description.content).unwrap_or_default());
+    channel.set_language(feed.language);
+    channel.set_last_build_date(feed.updated.map(|date| date.to_rfc2822()));
+    channel.set_image(feed.logo.map(|logo| rss::Image {
+        url: logo.uri,
+        title: logo.title.unwrap_or_else(|| channel.title().to_string()),
+        link: logo.link.map(|link| link.href).unwrap_or_else(|| channel.link().to_string()),
+        width: logo.width.map(|width| width.to_string()),
+        height: logo.height.map(|height| height.to_string()),
+        description: logo.description,
+    }));
+    channel.set_items(feed.entries.into_iter().map(atom_entry_to_item).collect::<Vec<_>>());
+    if channel.items().iter().any(|item| item.dublin_core_ext().is_some()) {
+        channel.namespaces.insert("dc".to_string(), rss::extension::dublincore::NAMESPACE.to_string());
+    }
+    Ok(channel)
+}
+
+/// Parse a RSS or Atom file
+///
+/// In auto mode the file is first parsed as RSS, then as Atom. If both fail the RSS error is
+/// returned.
+///
+/// Both parsers skip a byte order mark and transcode files declaring a different encoding than
+/// UTF-8 (e.g. ISO-8859-1), so this needs no special handling.
+fn parse_rss<R: BufRead>(mut reader: R, format: InputFormat, name: &str) -> Result<Channel, AppError> {
+    let msg = || format!("unparseable RSS file {}", name);
+
+    if let InputFormat::Atom = format {
+        return parse_atom(reader).map_err(|error| AppError::Atom(msg(), error));
+    }
+
+    // The data is needed twice, so keep it in memory
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)
+        .map_err(|error| AppError::Io(format!("unreadable RSS file {}", name), error))?;
+
+    let mut channel = match format {
+        InputFormat::Auto => Channel::read_from(&data[..])
+            .or_else(|error| parse_atom(&data[..]).map_err(|_| AppError::Rss(msg(), error)))?,
+        _ => Channel::read_from(&data[..]).map_err(|error| AppError::Rss(msg(), error))?,
+    };
+    fix_rdf_items(&mut channel, &data);
+    Ok(channel)
+}
+
+/// Find the rdf:about attributes of the entries of a RSS 1.0 (RDF) file
+///
+/// Returns None for other files
+fn rdf_item_ids(data: &[u8]) -> Option<Vec<Option<String>>> {
+    use quick_xml::events::Event;
+
+    let mut reader = quick_xml::Reader::from_reader(data);
+    let mut buf = Vec::new();
+    let mut ids = None;
+    loop {
+        match reader.read_event_into(&mut buf).ok()? {
+            Event::Start(element) | Event::Empty(element) => match (&mut ids, element.local_name().as_ref()) {
+                (None, b"RDF") => ids = Some(Vec::new()),
+                (None, _) => return None,
+                (Some(ids), b"item") => ids.push(element.attributes().flatten()
+                    .find(|attribute| attribute.key.local_name().as_ref() == b"about")
+                    .and_then(|attribute| attribute.decoded_and_normalized_value(Default::default(), reader.decoder()).ok())
+                    .map(|id| id.into_owned())),
+                _ => {}
+            },
+            Event::Eof => return ids,
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+/// Fill in the GUID and date of RSS 1.0 entries, so they can be merged like other entries
+///
+/// RSS 1.0 identifies entries by their rdf:about attribute and uses dc:date for the date. The
+/// rss crate parses those files but ignores the attribute.
+fn fix_rdf_items(channel: &mut Channel, data: &[u8]) {
+    let ids = match rdf_item_ids(data) {
+        Some(ids) if ids.len() == channel.items().len() => ids,
+        _ => return,
+    };
+
+    for (item, id) in channel.items_mut().iter_mut().zip(ids) {
+        if let (None, Some(id)) = (item.guid(), id) {
+            item.set_guid(Guid { value: id, permalink: false });
+        }
+
+        let date = item.dublin_core_ext()
+            .and_then(|dc| dc.dates().first())
+            .and_then(|date| parse_item_date(date));
+        if let (None, Some(date)) = (item.pub_date(), date) {
+            item.set_pub_date(date.to_rfc2822());
+        }
+    }
+}
+
+/// Read and parse a RSS file
+fn read_rss(path: &Path, http: &Http, format: InputFormat, opt: &ReadOpt) -> Result<Channel, AppError> {
+    let file = open_rss(path, http, opt)?;
+    parse_rss(file, format, &path.display().to_string())
+}
+
+/// Convert a RSS entry into an Atom entry
+///
+/// Atom requires an updated timestamp, entries without a date use the one of the feed
+fn item_to_atom_entry(item: &Item, feed_updated: atom_syndication::FixedDateTime) -> atom_syndication::Entry {
+    let date = item.pub_date().and_then(parse_item_date);
+
+    let mut entry = atom_syndication::Entry::default();
+    entry.set_id(dedup_key(item, None).or_else(|| item.title()).unwrap_or_default());
+    entry.set_title(item.title().unwrap_or_default());
+    entry.set_updated(date.unwrap_or(feed_updated));
+    entry.set_published(date);
+    let mut links = Vec::new();
+    if let Some(link) = item.link() {
+        links.push(atom_syndication::Link { href: link.to_string(), ..Default::default() });
+    }
+    if let Some(enclosure) = item.enclosure() {
+        links.push(atom_syndication::Link {
+            href: enclosure.url().to_string(),
+            rel: "enclosure".to_string(),
+            mime_type: Some(enclosure.mime_type().to_string()).filter(|mime_type| !mime_type.is_empty()),
+            length: Some(enclosure.length().to_string()).filter(|length| length != "0"),
+            ..Default::default()
+        });
+    }
+    entry.set_links(links);
+    entry.set_summary(item.description().map(atom_syndication::Text::html));
+    entry.set_authors(item_author(item)
+        .map(|name| atom_syndication::Person { name: name.to_string(), ..Default::default() })
+        .into_iter()
+        .collect::<Vec<_>>());
+    entry
+}
+
+/// Convert the merged RSS channel into an Atom feed
+///
+/// The feed is considered updated when its newest entry was. Without any dated entries the
+/// dates of the channel are used, so that the same input results in the same output.
+fn channel_to_atom(channel: &Channel) -> atom_syndication::Feed {
+    let updated = channel.items().iter()
+        .filter_map(|item| item.pub_date().and_then(parse_item_date))
+        .max()
+        .or_else(|| channel.last_build_date().or(channel.pub_date()).and_then(parse_item_date))
+        .unwrap_or_else(|| now().fixed_offset());
+
+    let mut feed = atom_syndication::Feed::default();
+    feed.set_id(channel.link());
+    feed.set_title(channel.title());
+    feed.set_updated(updated);
+    feed.set_links(vec![atom_syndication::Link { href: channel.link().to_string(), ..Default::default() }]);
+    if !channel.description().is_empty() {
+        feed.set_subtitle(atom_syndication::Text::plain(channel.description()));
+    }
+    feed.set_lang(channel.language().map(|language| language.to_string()));
+    feed.set_logo(channel.image().map(|image| image.url().to_string()));
+    feed.set_entries(channel.items().iter()
+        .map(|item| item_to_atom_entry(item, updated))
+        .collect::<Vec<_>>());
+    feed
+}
+
+/// A JSON Feed, see https://jsonfeed.org/version/1.1
+#[derive(serde::Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    home_page_url: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    description: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<&'a str>,
+    items: Vec<JsonFeedItem<'a>>,
+}
+
+/// An entry of a JSON Feed
+#[derive(serde::Serialize)]
+struct JsonFeedItem<'a> {
+    id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_html: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date_published: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<JsonFeedAuthor<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<JsonFeedAttachment<'a>>,
+}
+
+/// An author of a JSON Feed entry
+#[derive(serde::Serialize)]
+struct JsonFeedAuthor<'a> {
+    name: &'a str,
+}
+
+/// An enclosure of a JSON Feed entry
+#[derive(serde::Serialize)]
+struct JsonFeedAttachment<'a> {
+    url: &'a str,
+    mime_type: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_in_bytes: Option<u64>,
+}
+
+/// Convert the merged RSS channel into a JSON Feed
+fn channel_to_json(channel: &Channel) -> JsonFeed<'_> {
+    JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: channel.title(),
+        home_page_url: channel.link(),
+        description: channel.description(),
+        language: channel.language(),
+        items: channel.items().iter()
+            .map(|item| JsonFeedItem {
+                // JSON Feed requires an id
+                id: dedup_key(item, None).or_else(|| item.title()).unwrap_or_default(),
+                url: item.link(),
+                title: item.title(),
+                content_html: item.description(),
+                date_published: item.pub_date().and_then(parse_item_date).map(|date| date.to_rfc3339()),
+                authors: item_author(item).map(|name| JsonFeedAuthor { name }).into_iter().collect(),
+                attachments: item.enclosure().into_iter()
+                    .map(|enclosure| JsonFeedAttachment {
+                        url: enclosure.url(),
+                        mime_type: enclosure.mime_type(),
+                        size_in_bytes: enclosure.length().parse().ok().filter(|&length| length > 0),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+/// Write the merged RSS in the requested format
+fn write_channel<W: Write>(channel: &Channel, format: OutputFormat, indent: Option<usize>, mut writer: W, msg: &str) -> Result<(), AppError> {
+    match format {
+        OutputFormat::Rss => {
+            match indent {
+                Some(indent) => channel.pretty_write_to(&mut writer, b' ', indent),
+                None => channel.write_to(&mut writer),
+            }.map_err(|error| AppError::Rss(msg.to_string(), error))?;
+        }
+        OutputFormat::Atom => {
+            let config = atom_syndication::WriteConfig { indent_size: indent, ..Default::default() };
+            channel_to_atom(channel).write_with_config(&mut writer, config)
+                .map_err(|error| AppError::AtomWrite(msg.to_string(), error))?;
+        }
+        OutputFormat::Json => {
+            let feed = channel_to_json(channel);
+            match indent {
+                Some(indent) => {
+                    let indent = vec![b' '; indent];
+                    let formatter = serde_json::ser::PrettyFormatter::with_indent(&indent);
+                    serde::Serialize::serialize(&feed, &mut serde_json::Serializer::with_formatter(&mut writer, formatter))
+                }
+                None => serde_json::to_writer(&mut writer, &feed),
+            }.map_err(|error| AppError::Json(msg.to_string(), error))?;
+            writer.write_all(b"\n").map_err(|error| AppError::Io(msg.to_string(), error))?;
+        }
+    }
+    Ok(())
+}
+
+/// Indentation of the merged file, if any
+///
+/// Without --pretty or --compact only a merged file piped into another program is written compact.
+fn output_indent(opt: &Opt) -> Option<usize> {
+    let piped = opt.stdout && !io::stdout().is_terminal();
+    let pretty = opt.pretty || !(opt.compact || piped);
+    if pretty { Some(opt.indent) } else { None }
+}
+
+/// Whether the newest entries of the merged RSS come last, due to --reverse or --insert bottom
+/// without sorting
+fn newest_last(opt: &Opt) -> bool {
+    let bottom = opt.insert == Insert::Bottom && opt.no_sort && opt.shuffle.is_none();
+    opt.reverse != bottom
+}
+
+/// Drop the oldest entries until the serialized channel fits in `max_bytes`
+///
+/// The size is not linear in the number of entries because of e.g. the indentation, so the
+/// channel is serialized with a varying number of entries, binary searching for the most entries
+/// that fit. The build dates have to be set already when the RSS `changed`; otherwise they are set
+/// once entries are dropped, as that changes the RSS. Returns the number of dropped entries.
+fn limit_bytes(channel: &mut Channel, max_bytes: usize, changed: bool, opt: &Opt) -> Result<usize, AppError> {
+    if serialize_output(channel, opt)?.len() <= max_bytes {
+        return Ok(0)
+    }
+    if !changed {
+        update_builddate(channel, opt);
+    }
+
+    let items = std::mem::take(&mut channel.items);
+    let newest = |count: usize| if newest_last(opt) { &items[items.len() - count..] } else { &items[..count] };
+    let mut fits = |count: usize| -> Result<bool, AppError> {
+        channel.set_items(newest(count));
+        Ok(serialize_output(channel, opt)?.len() <= max_bytes)
+    };
+
+    // Invariant: low entries fit, high entries do not
+    let (mut low, mut high) = (0, items.len());
+    if !fits(high)? {
+        while high - low > 1 {
+            let middle = low + (high - low) / 2;
+            if fits(middle)? {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        high = low;
+    }
+    channel.set_items(newest(high));
+    Ok(items.len() - high)
+}
+
+/// Replace the entries of a channel by what `cmd` makes of them, see --filter-cmd
+fn filter_items(channel: &mut Channel, cmd: &str, path: &Path) -> Result<(), AppError> {
+    let msg = || format!("Cannot run filter command for {}", path.display());
+    let items = std::mem::take(&mut channel.items);
+    let nr_items = items.len();
+
+    for item in items {
+        let mut single = channel.clone();
+        single.set_items(vec![item]);
+        let mut input = Vec::new();
+        single.write_to(&mut input).map_err(|error| AppError::Rss(msg(), error))?;
+
+        let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+        let mut child = std::process::Command::new(shell)
+            .arg(flag)
+            .arg(cmd)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .map_err(|error| AppError::Io(msg(), error))?;
+
+        // Feed stdin from another thread, the command might write before reading everything
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let output = std::thread::scope(|scope| {
+            scope.spawn(move || stdin.write_all(&input));
+            child.wait_with_output()
+        }).map_err(|error| AppError::Io(msg(), error))?;
+
+        if !output.status.success() {
+            continue
+        }
+        match Channel::read_from(&output.stdout[..]) {
+            Ok(filtered) => channel.items.extend(filtered.items),
+            Err(error) => log::warn!("Dropping entry of {} as the filter command wrote invalid RSS: {}",
+                                     path.display(), error),
+        }
+    }
+
+    if channel.items.len() != nr_items {
+        log::debug!("Filter command changed the {} entries of {} into {}", nr_items, path.display(), channel.items.len());
+    }
+    Ok(())
+}
+
+/// Use \r\n for all line breaks, keeping those which already are
+fn to_crlf(data: &[u8]) -> Vec<u8> {
+    let mut converted = Vec::with_capacity(data.len() + data.len() / 32);
+    for (i, &byte) in data.iter().enumerate() {
+        if byte == b'\n' && (i == 0 || data[i - 1] != b'\r') {
+            converted.push(b'\r');
+        }
+        converted.push(byte);
+    }
+    converted
+}
+
+/// Check that the serialized merged RSS can be parsed again
+///
+/// This prevents overwriting the main RSS file with garbage
+fn verify_output(data: &[u8], format: OutputFormat) -> Result<(), AppError> {
+    let msg = || "Merged RSS is invalid, not writing it".to_string();
+
+    match format {
+        OutputFormat::Rss => {
+            Channel::read_from(data).map_err(|error| AppError::Rss(msg(), error))?;
+        }
+        OutputFormat::Atom | OutputFormat::Json => {
+            feed_rs::parser::parse(data).map_err(|error| AppError::Atom(msg(), error))?;
+        }
+    }
+    Ok(())
+}
+
+/// Merge the RSS files, returns whether any new entries were merged
+fn run_app(opt: &Opt) -> Result<bool, AppError> {
+    let started = now();
+
+    // Only missing when using --config, main() checks it was provided there
+    let input = opt.input.as_deref().expect("main RSS file is required");
+    let from_stdin = input == Path::new("-");
+    let main_name = if from_stdin { "<stdin>".to_string() } else { input.display().to_string() };
+
+    // The merged RSS is written back into the main RSS file by default, which is not possible
+    // when it was read from stdin or fetched from an URL
+    if (from_stdin || is_url(input)) && opt.output.is_none() && !opt.stdout && !opt.dry_run && !opt.count_only {
+        return Err(AppError::Config(
+            "--output or --stdout is required when the main RSS is not a local file".to_string()));
+    }
+
+    if opt.max_entries > 0 && opt.keep_percent.is_some() {
+        return Err(AppError::Config("-l and --keep-percent cannot be used together".to_string()));
+    }
+    if opt.keep_percent.is_some_and(|percent| percent > 100) {
+        return Err(AppError::Config("--keep-percent must be at most 100".to_string()));
+    }
+
+    if opt.max_entries > 0 && opt.min_entries > opt.max_entries {
+        return Err(AppError::Config(format!(
+            "--min-entries {} is more than the maximum of {} entries", opt.min_entries, opt.max_entries)));
+    }
+
+    if opt.stdout && opt.stats_json.as_deref() == Some(Path::new("-")) {
+        return Err(AppError::Config("--stats-json cannot write to stdout when using --stdout".to_string()));
+    }
+
+    let http = build_http(&opt.read)?;
+
+    log::debug!("Reading original RSS: {}", main_name);
+
+    let channel = if from_stdin {
+        let stdin = decompress(Box::new(BufReader::new(io::stdin().lock())))
+            .map_err(|error| AppError::Io("unreadable RSS file <stdin>".to_string(), error))?;
+        parse_rss(stdin, opt.read.input_format, "<stdin>")?
+    } else {
+        read_rss(input, &http, opt.read.input_format, &opt.read)?
+    };
+
+    let template = match &opt.channel_template {
+        Some(path) => {
+            log::debug!("Reading channel template: {}", path.display());
+            let template = read_rss(path, &http, InputFormat::Auto, &opt.read)?;
+            if !template.items().is_empty() {
+                log::warn!("Ignoring the {} entries of channel template {}", template.items().len(), path.display());
+            }
+            Some(template)
+        }
+        None => None,
+    };
+
+    let seen_db = match &opt.seen_db {
+        Some(path) => read_seen_db(path)?,
+        None => Vec::new(),
+    };
+
+    // Additional RSS files which could be read, together with their filename
+    let mut filenames = Vec::new();
+    let mut extras = Vec::new();
+
+    let files = collect_files(&opt.files, opt.feeds_from.as_deref(), &opt.read)?;
+
+    // The progress bar is drawn on stderr and only if that is a terminal. Messages are printed
+    // while the bar is suspended so they do not get mixed up.
+    let progress = if files.len() > 5 && !opt.no_progress {
+        indicatif::ProgressBar::new(files.len() as u64)
+    } else {
+        indicatif::ProgressBar::hidden()
+    };
+
+    // Merging the main RSS into itself does nothing, it is probably a mistake
+    let mut to_read = Vec::new();
+    for (rss_filename, format) in files {
+        if !from_stdin && same_source(&rss_filename, input) {
+            let msg = format!("Skipping {} as it is the main RSS file", rss_filename.display());
+            if opt.read.strict {
+                return Err(AppError::Strict(msg));
+            }
+            progress.suspend(|| log::warn!("{}", msg));
+            progress.inc(1);
+            continue
+        }
+        to_read.push((rss_filename, format));
+    }
+
+    let read_extra = |rss_filename: &Path, format| {
+        progress.suspend(|| log::debug!("Reading additional RSS: {}", rss_filename.display()));
+        let extra = read_rss(rss_filename, &http, format, &opt.read).and_then(|mut extra| {
+            if let Some(cmd) = &opt.filter_cmd {
+                filter_items(&mut extra, cmd, rss_filename)?;
+            }
+            Ok(extra)
+        });
+        progress.inc(1);
+        extra
+    };
+
+    // Merged in the order of the files, however they were read
+    let read_extras = read_parallel(&to_read, opt.jobs, read_extra);
+    for ((rss_filename, _), extra) in to_read.into_iter().zip(read_extras) {
+        match extra {
+            Ok(extra) => {
+                extras.push(extra);
+                filenames.push(rss_filename);
+            }
+            // Only fatal because of --strict, so it is not an ordinary error
+            Err(error) if opt.read.strict => return Err(AppError::Strict(error.to_string())),
+            Err(error) => log::warn!("Skipping {}", error),
+        }
+    }
+    progress.finish_and_clear();
+
+    let options = CombineOptions {
+        max_entries: opt.max_entries,
+        keep_percent: opt.keep_percent,
+        limit_per_source: opt.limit_per_source,
+        fair_truncate: opt.fair_truncate,
+        dedup_key: opt.dedup_key,
+        normalize_guid: opt.normalize_guid,
+        dedup_ignore_case: opt.dedup_ignore_case,
+        dedup_by_content: opt.dedup_by_content,
+        dedup_by_enclosure: opt.dedup_by_enclosure,
+        dedup_by_media_url: opt.dedup_by_media_url,
+        title_dedup: opt.title_dedup,
+        keep_guidless: opt.keep_guidless,
+        sort: !opt.no_sort,
+        shuffle: opt.shuffle.map(|seed| seed.unwrap_or_else(rand::random)),
+        reverse: opt.reverse,
+        primary: if opt.keep_main_metadata { Primary::First } else { opt.primary },
+        image_from: opt.image_from,
+        insert: opt.insert,
+        ttl: opt.ttl,
+        since: opt.since.map(Since::resolve),
+        now: Some(now().fixed_offset()),
+        max_age: opt.max_age,
+        drop_undated: opt.drop_undated,
+        expiry_element: opt.expiry_element.clone(),
+        append_only: opt.append_only,
+        include_categories: opt.include_categories.clone(),
+        exclude_categories: opt.exclude_categories.clone(),
+        category_case_insensitive: opt.category_case_insensitive,
+        title_match: opt.title_match.clone(),
+        title_reject: opt.title_reject.clone(),
+        prefix_title: opt.prefix_title.clone(),
+        prefix_all: opt.prefix_all,
+        seen_guids: seen_db.iter().cloned().collect(),
+        strip_html: opt.strip_html,
+        max_description_chars: opt.max_description_chars,
+        absolute_links: opt.absolute_links,
+        base_url: opt.base_url.clone(),
+        default_author: opt.default_author.clone(),
+        merge_comments: opt.merge_comments,
+        merge_update: opt.merge_update,
+        add_source: opt.add_source,
+    };
+    if opt.count_only {
+        print_counts(&channel, &main_name, &extras, &filenames, &options);
+        return Ok(false)
+    }
+
+    // Also the entries of the main RSS are seen, even if they are dropped by this merge
+    let main_guids: Vec<_> = match opt.seen_db {
+        Some(_) => channel.items().iter().rev()
+            .filter_map(|item| dedup_key(item, opt.dedup_key))
+            .map(|guid| options.compare_key(guid))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let only_new = opt.only_new.as_ref().map(|_| Channel { items: Vec::new(), ..channel.clone() });
+
+    // Split before limiting the entries, so that -l and --keep-percent apply to each file
+    let split_items = opt.split_by_category.as_ref().map(|_| {
+        let options = CombineOptions { max_entries: 0, keep_percent: None, ..options.clone() };
+        combine(channel.clone(), extras.clone(), &options).items
+    });
+
+    let (mut channel, stats) = combine_with_stats(channel, extras, &options);
+
+    // Keep only the entries and namespaces of the merged channel
+    if let Some(mut template) = template {
+        template.set_items(std::mem::take(&mut channel.items));
+        channel.namespaces.append(&mut template.namespaces);
+        template.namespaces = std::mem::take(&mut channel.namespaces);
+        // An explicit --ttl still applies
+        if opt.ttl.is_some() {
+            template.set_ttl(channel.ttl.take());
+        }
+        channel = template;
+    }
+
+    // The build dates count towards --max-bytes, so set them before limiting
+    if stats.changed() {
+        update_builddate(&mut channel, opt);
+    }
+    let dropped_bytes = match opt.max_bytes {
+        0 => 0,
+        max_bytes => limit_bytes(&mut channel, max_bytes, stats.changed(), opt)?,
+    };
+
+    // Mention anything weird in the data
+    if stats.missing_guids > 0 {
+        let field = match opt.dedup_key {
+            Some(DedupKey::Guid) => "GUID",
+            Some(DedupKey::Link) => "link",
+            Some(DedupKey::Title) => "title",
+            None => "GUID or link",
+        };
+        problem!(opt.read, "Ignored {} RSS entries without a {}", stats.missing_guids, field);
+    }
+
+    // Only the first entry of a repeated GUID is kept, which is easily missed
+    if opt.read.verbose || opt.read.strict {
+        let repeated = std::iter::once((main_name.clone(), &stats.main_repeated_guids))
+            .chain(filenames.iter().map(|filename| filename.display().to_string()).zip(&stats.file_repeated_guids));
+        for (name, guids) in repeated.filter(|(_, guids)| !guids.is_empty()) {
+            problem!(opt.read, "Duplicate GUIDs within {}: {}", name, guids.join(", "));
+        }
+    }
+
+    for guid in &stats.new_guids {
+        log::debug!("New entry: {}", guid);
+    }
+    if stats.dropped_old > 0 {
+        log::debug!("Dropped {} entries as too old", stats.dropped_old);
+    }
+    if stats.dropped_expired > 0 {
+        log::debug!("Dropped {} expired entries", stats.dropped_expired);
+    }
+    if stats.dropped_max_entries > 0 {
+        match opt.keep_percent {
+            Some(percent) => log::debug!("Restricting RSS size to newest {}% of the entries", percent),
+            None => log::debug!("Restricting RSS size to newest {} entries", opt.max_entries),
+        }
+    }
+
+    // Dropping most of the entries is more likely a typo in -l than intended
+    let nr_combined = opt.max_entries + stats.dropped_max_entries;
+    if opt.keep_percent.is_none() && stats.dropped_max_entries * 2 > nr_combined {
+        log::warn!("-l {} dropped {} of the {} combined entries, keeping {}; the limit might be too low",
+                   opt.max_entries, stats.dropped_max_entries, nr_combined, opt.max_entries);
+    }
+    if dropped_bytes > 0 {
+        log::debug!("Dropped {} entries to stay within {} bytes", dropped_bytes, opt.max_bytes);
+    }
+
+    if opt.read.verbose {
+        print_stats(&stats, &filenames);
+    }
+
+    if opt.dedup_report {
+        let name = |source: usize| match source {
+            0 => main_name.clone(),
+            source => filenames[source - 1].display().to_string(),
+        };
+        for duplicate in &stats.duplicate_entries {
+            let of = match duplicate.of {
+                DuplicateOf::Key => "key",
+                DuplicateOf::Content => "content",
+                DuplicateOf::Enclosure => "enclosure",
+                DuplicateOf::Title => "title",
+                DuplicateOf::Media => "media",
+            };
+            eprintln!("duplicate\t{}\t{}\t{}\t{}", duplicate.key, name(duplicate.source), name(duplicate.earlier_source), of);
+        }
+    }
+
+    if let Some(path) = &opt.stats_json {
+        write_stats_json(path, &stats, &main_name, &filenames, channel.items().len(), started)?;
+    }
+
+    // We only rewrite the RSS in case there are additional entries or entries were removed
+    //
+    // Updates of any other field is not important. When writing to stdout the RSS is always
+    // written, as there is no existing file to keep.
+    let changed = stats.changed() || dropped_bytes > 0;
+
+    // Empty means no entries are left after merging, filtering and limiting, also when the main
+    // RSS had no entries either
+    if channel.items().is_empty() {
+        match opt.on_empty {
+            OnEmpty::Fail => return Err(AppError::Empty("The merged RSS has no entries".to_string())),
+            OnEmpty::Keep => {
+                log::warn!("Not writing merged RSS without any entries");
+                return Ok(false)
+            }
+            OnEmpty::Write => (),
+        }
+    }
+
+    // Keep the existing RSS instead of replacing it with an almost empty one
+    if channel.items().len() < opt.min_entries {
+        log::warn!("Not writing merged RSS with {} entries, fewer than the minimum of {}",
+                 channel.items().len(), opt.min_entries);
+        return Ok(false)
+    }
+
+    // The new entries which are left after --max-bytes. When the newest entries are at the end,
+    // it dropped the first ones.
+    let offset = if newest_last(opt) { dropped_bytes } else { 0 };
+    let new_positions: Vec<_> = stats.new_positions.iter()
+        .filter_map(|position| position.checked_sub(offset))
+        .filter(|&position| position < channel.items().len())
+        .collect();
+
+    if opt.dry_run {
+        eprintln!("Dry run: would add {} new entries, resulting in {} entries",
+                  new_positions.len(), channel.items().len());
+        return Ok(changed)
+    }
+
+    if let (Some(path), Some(only_new)) = (&opt.only_new, only_new) {
+        write_only_new(only_new, &channel, &new_positions, path, opt)?;
+    }
+
+    // Another output file still has to be created or brought up to date, write_merged leaves it
+    // alone when it is identical already
+    let other_output = opt.output.as_deref().is_some_and(|output| !same_source(output, input));
+    let written = (changed || opt.stdout || other_output || channel.items().is_empty())
+        && write_merged(&channel, changed, input, opt)?;
+    if written {
+        if let (Some(dir), Some(items)) = (&opt.split_by_category, split_items) {
+            write_split(&channel, items, dir, opt)?;
+        }
+
+        // Meant for scripts, so do not change this format
+        log::info!("rss-combine: wrote {} items ({} new)", channel.items().len(), new_positions.len());
+    } else {
+        log::debug!("No changes made");
+    }
+
+    // Only after the merged RSS was written, otherwise the new entries would be lost for good
+    if let Some(path) = &opt.seen_db {
+        update_seen_db(path, seen_db, main_guids.into_iter().chain(stats.new_guids), opt.seen_db_max)?;
+    }
+
+    // An identical output file means nothing changed after all
+    Ok(changed && written)
+}
+
+/// Read the GUIDs stored by --seen-db, oldest first
+///
+/// A missing file is the same as an empty one, which is the case on the first run
+fn read_seen_db(path: &Path) -> Result<Vec<String>, AppError> {
+    match std::fs::read_to_string(path) {
+        Ok(data) => Ok(data.lines().filter(|line| !line.is_empty()).map(str::to_string).collect()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(error) => Err(AppError::Io(format!("Cannot read seen database {}", path.display()), error)),
+    }
+}
+
+/// Add the GUIDs of the main RSS and the merged entries to the --seen-db file
+///
+/// The oldest GUIDs are dropped when there are more than `max`. The file is only rewritten when
+/// anything changed.
+fn update_seen_db(path: &Path, mut guids: Vec<String>, keys: impl Iterator<Item = String>, max: usize) -> Result<(), AppError> {
+    let mut known: std::collections::HashSet<_> = guids.iter().cloned().collect();
+    let nr_guids = guids.len();
+    guids.extend(keys.filter(|guid| known.insert(guid.clone())));
+
+    // Nothing new to store
+    if guids.len() == nr_guids && (max == 0 || guids.len() <= max) && path.exists() {
+        return Ok(())
+    }
+
+    if max > 0 && guids.len() > max {
+        guids.drain(..guids.len() - max);
+    }
+
+    let msg = || format!("Cannot store seen database {}", path.display());
+    let mut data = guids.join("\n");
+    data.push('\n');
+    let mut outfile = tempfile_fast::Sponge::new_for(path).map_err(|error| AppError::Io(msg(), error))?;
+    outfile.write_all(data.as_bytes()).map_err(|error| AppError::Io(msg(), error))?;
+    outfile.commit().map_err(|error| AppError::Io(msg(), error))
+}
+
+/// Let downstream caches know the RSS changed, unless --no-update-builddate
+fn update_builddate(channel: &mut Channel, opt: &Opt) {
+    if !opt.no_update_builddate {
+        let now = now().to_rfc2822();
+        if channel.pub_date().is_some() {
+            channel.set_pub_date(now.clone());
+        }
+        channel.set_last_build_date(now);
+    }
+}
+
+/// Serialize the merged RSS and write it to the output file or stdout
+///
+/// The build dates must already be updated when the RSS `changed`. Returns false if the output
+/// file was left alone as it already contains exactly this RSS.
+fn write_merged(channel: &Channel, changed: bool, input: &Path, opt: &Opt) -> Result<bool, AppError> {
+    if opt.stdout {
+        let data = serialize_merged(channel, opt)?;
+        check_size(&data, opt)?;
+        io::stdout().lock().write_all(&data)
+            .map_err(|error| AppError::Io("Cannot write merged RSS to stdout".to_string(), error))?;
+        write_checksum(&data, opt)?;
+        return Ok(true)
+    }
+
+    // And write the new file
+    //
+    // The Sponge writes to a temporary file next to the output file and renames it into place on
+    // commit, so the output is never left half written. Being in the same directory, it is also on
+    // the same drive, which a rename on Windows requires
+    let output = opt.output.as_deref().unwrap_or(input);
+    let msg = || format!("Cannot store merged RSS into {}", output.display());
+
+    // The rename would replace a symlink itself, instead replace the file it points to
+    let output = match opt.no_follow_symlinks {
+        true => output.to_path_buf(),
+        false => resolve_symlinks(output).map_err(|error| AppError::Io(msg(), error))?,
+    };
+
+    // Rewriting an identical file would only make downstream caches fetch it again. The dates that
+    // would be updated are taken from an existing RSS file, as those alone would make it differ.
+    if let Ok(existing) = std::fs::read(&output) {
+        let mut unchanged = channel.clone();
+        if changed && !opt.no_update_builddate {
+            if let Ok(existing_channel) = Channel::read_from(&existing[..]) {
+                if channel.pub_date().is_some() {
+                    unchanged.set_pub_date(existing_channel.pub_date().map(str::to_string));
+                }
+                unchanged.set_last_build_date(existing_channel.last_build_date().map(str::to_string));
+            }
+        }
+        if serialize_merged(&unchanged, opt)? == existing {
+            log::debug!("Not rewriting {} as it is unchanged", output.display());
+            write_checksum(&existing, opt)?;
+            return Ok(false)
+        }
+    }
+
+    let data = serialize_merged(channel, opt)?;
+    check_size(&data, opt)?;
+    store_file(&output, &data).map_err(|error| AppError::Io(msg(), error))?;
+    write_checksum(&data, opt)?;
+    Ok(true)
+}
+
+/// Warn about a merged RSS bigger than --warn-size, as some readers cannot handle those
+fn check_size(data: &[u8], opt: &Opt) -> Result<(), AppError> {
+    if let Some(warn_size) = opt.warn_size.filter(|&warn_size| data.len() > warn_size) {
+        problem!(opt.read, "The merged RSS is {} bytes, more than the --warn-size of {} bytes", data.len(), warn_size);
+    }
+    Ok(())
+}
+
+/// Write the SHA-256 of the written bytes for --checksum
+fn write_checksum(data: &[u8], opt: &Opt) -> Result<(), AppError> {
+    use sha2::Digest;
+    use std::fmt::Write;
+
+    let Some(path) = &opt.checksum else { return Ok(()) };
+    let mut hex = sha2::Sha256::digest(data).iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    });
+    hex.push('\n');
+    store_file(path, hex.as_bytes())
+        .map_err(|error| AppError::Io(format!("Cannot store checksum into {}", path.display()), error))
+}
+
+/// Serialize the merged RSS as it should be written
+fn serialize_merged(channel: &Channel, opt: &Opt) -> Result<Vec<u8>, AppError> {
+    let data = serialize_output(channel, opt)?;
+    if !opt.no_verify {
+        verify_output(&data, opt.output_format)?;
+    }
+    Ok(data)
+}
+
+/// Serialize the merged RSS without verifying it, e.g. to know its size
+fn serialize_output(channel: &Channel, opt: &Opt) -> Result<Vec<u8>, AppError> {
+    let mut data = Vec::new();
+    write_channel(channel, opt.output_format, output_indent(opt), &mut data, "Cannot serialize merged RSS")?;
+    if opt.crlf {
+        data = to_crlf(&data);
+    }
+    Ok(data)
+}
+
+/// The file a path points to after following any symlinks
+///
+/// Unlike [`std::fs::canonicalize`] the file does not have to exist, so a symlink to a file that
+/// is yet to be written works as well.
+fn resolve_symlinks(path: &Path) -> io::Result<PathBuf> {
+    let mut path = path.to_path_buf();
+    // The same limit as Linux, to stop at symlink loops
+    for _ in 0..40 {
+        match std::fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let target = std::fs::read_link(&path)?;
+                path = path.parent().unwrap_or(Path::new("")).join(target);
+            }
+            _ => return Ok(path),
+        }
+    }
+    Err(io::Error::other("too many levels of symbolic links"))
+}
+
+/// Replace a file without ever leaving it half written
+fn store_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut outfile = tempfile_fast::Sponge::new_for(path)?;
+    outfile.write_all(data)?;
+    outfile.commit()
+}
+
+/// Turn a category into a file name, keeping only lowercase letters, digits and dashes
+fn category_filename(category: &str) -> String {
+    let mut name = String::new();
+    for c in category.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            name.push(c);
+        } else if !name.is_empty() && !name.ends_with('-') {
+            name.push('-');
+        }
+    }
+    let name = name.trim_end_matches('-');
+    if name.is_empty() { "category".to_string() } else { name.to_string() }
+}
+
+/// Write the merged entries into one file per category for --split-by-category
+///
+/// Entries go into the file of their first category, those without a category into
+/// uncategorized. Categories resulting in the same file name are written together.
+fn write_split(channel: &Channel, items: Vec<Item>, dir: &Path, opt: &Opt) -> Result<(), AppError> {
+    let extension = match opt.output_format {
+        OutputFormat::Json => "json",
+        OutputFormat::Rss | OutputFormat::Atom => "xml",
+    };
+
+    let mut split: std::collections::BTreeMap<String, Vec<Item>> = std::collections::BTreeMap::new();
+    for item in items {
+        let name = item.categories().first()
+            .map_or_else(|| "uncategorized".to_string(), |category| category_filename(category.name()));
+        split.entry(name).or_default().push(item);
+    }
+
+    std::fs::create_dir_all(dir)
+        .map_err(|error| AppError::Io(format!("Cannot create directory {}", dir.display()), error))?;
+    for (name, mut items) in split {
+        // Rounded the same way as for the merged RSS
+        let max_entries = match opt.keep_percent {
+            Some(percent) => (items.len() * usize::from(percent) + 50) / 100,
+            None if opt.max_entries > 0 => opt.max_entries,
+            None => items.len(),
+        };
+        if items.len() > max_entries {
+            if newest_last(opt) {
+                items.drain(..items.len() - max_entries);
+            } else {
+                items.truncate(max_entries);
+            }
+        }
+
+        let path = dir.join(format!("{}.{}", name, extension));
+        let mut category_channel = channel.clone();
+        category_channel.set_items(items);
+        let data = serialize_merged(&category_channel, opt)?;
+        store_file(&path, &data)
+            .map_err(|error| AppError::Io(format!("Cannot store merged RSS into {}", path.display()), error))?;
+        log::debug!("Wrote {} entries into {}", category_channel.items().len(), path.display());
+    }
+
+    Ok(())
+}
+
+/// Write the entries at these positions of the merged RSS into their own file, with the channel
+/// fields of `only_new`
+fn write_only_new(mut only_new: Channel, channel: &Channel, positions: &[usize], path: &Path, opt: &Opt) -> Result<(), AppError> {
+    only_new.set_items(positions.iter().filter_map(|&position| channel.items().get(position)).cloned().collect::<Vec<_>>());
+    only_new.namespaces = channel.namespaces.clone();
+    if !opt.no_update_builddate {
+        only_new.set_last_build_date(now().to_rfc2822());
+    }
+
+    let data = serialize_merged(&only_new, opt)?;
+    store_file(path, &data)
+        .map_err(|error| AppError::Io(format!("Cannot store new entries into {}", path.display()), error))?;
+    log::debug!("Wrote {} new entries into {}", only_new.items().len(), path.display());
+    Ok(())
+}
+
+/// Parse all files, reporting whether they are all valid
+fn run_validate(opt: &InspectOpt) -> Result<bool, AppError> {
+    let http = build_http(&opt.read)?;
+    let mut valid = true;
+
+    for (filename, format) in collect_files(&opt.files, opt.feeds_from.as_deref(), &opt.read)? {
+        match read_rss(&filename, &http, format, &opt.read) {
+            Ok(channel) => println!("{}: OK, {} entries", filename.display(), channel.items().len()),
+            Err(error) => {
+                eprintln!("error: {}", error);
+                valid = false;
+            }
+        }
+    }
+
+    Ok(valid)
+}
+
+/// Print a table of the entries of all files
+fn run_dump(opt: &InspectOpt) -> Result<(), AppError> {
+    let http = build_http(&opt.read)?;
+    let mut rows = Vec::new();
+
+    for (filename, format) in collect_files(&opt.files, opt.feeds_from.as_deref(), &opt.read)? {
+        let channel = match read_rss(&filename, &http, format, &opt.read) {
+            Ok(channel) => channel,
+            Err(error) if opt.read.strict => return Err(AppError::Strict(error.to_string())),
+            Err(error) => {
+                log::warn!("Skipping {}", error);
+                continue
+            }
+        };
+        for item in channel.items() {
+            let date = match item.pub_date() {
+                Some(date) => parse_item_date(date).map(|date| date.to_rfc3339()).unwrap_or_else(|| date.to_string()),
+                None => "-".to_string(),
+            };
+            rows.push([filename.display().to_string(),
+                       dedup_key(item, None).unwrap_or("-").to_string(),
+                       date,
+                       item.title().unwrap_or("-").to_string()]);
+        }
+    }
+
+    let header = ["FILE", "GUID", "DATE", "TITLE"].map(str::to_string);
+    let mut widths = header.clone().map(|column| column.chars().count());
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
+
+    let mut stdout = io::stdout().lock();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = format!("{:<w0$}  {:<w1$}  {:<w2$}  {}", row[0], row[1], row[2], row[3],
+                           w0 = widths[0], w1 = widths[1], w2 = widths[2]);
+        writeln!(stdout, "{}", line.trim_end())
+            .map_err(|error| AppError::Io("Cannot write to stdout".to_string(), error))?;
+    }
+
+    Ok(())
+}
+
+/// Run the combine subcommand, returning the exit code
+fn run_combine(mut opt: Opt, matches: &ArgMatches) -> Result<ExitCode, AppError> {
+    if let Some(config) = opt.config.clone() {
+        apply_config(&mut opt, matches, &config)?;
+
+        // The config file has to provide whatever is required but not given on the command line
+        if opt.input.is_none() || (opt.files.is_empty() && opt.feeds_from.is_none()) {
+            let missing = if opt.input.is_none() { "<INPUT>" } else { "<FILES>... or --feeds-from" };
+            Cli::command().error(clap::error::ErrorKind::MissingRequiredArgument,
+                                 format!("{} not given on the command line or in {}", missing, config.display()))
+                .exit();
+        }
+    }
+    init_logger(&opt.read);
+    if let Some(now) = opt.now {
+        NOW.set(now.to_utc()).expect("--now is only set once");
+    }
+
+    Ok(match run_app(&opt)? {
+        // Similar to diff, optionally report whether anything changed
+        true if opt.exit_code_on_change => ExitCode::Changed,
+        _ => ExitCode::Success,
+    })
+}
+
+fn main() {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+
+    if let Some(shell) = cli.generate_completions {
+        clap_complete::generate(shell, &mut Cli::command(), "rss-combine", &mut io::stdout());
+        return
+    }
+
+    let result = match cli.command {
+        None => run_combine(cli.combine, &matches),
+        Some(Command::Combine(opt)) => {
+            run_combine(*opt, matches.subcommand_matches("combine").expect("combine subcommand was used"))
+        }
+        Some(Command::Validate(opt)) => {
+            init_logger(&opt.read);
+            run_validate(&opt).map(|valid| if valid { ExitCode::Success } else { ExitCode::Invalid })
+        }
+        Some(Command::Dump(opt)) => {
+            init_logger(&opt.read);
+            run_dump(&opt).map(|()| ExitCode::Success)
+        }
+    };
+
+    // Errors parsing the command line exit with 2 (ExitCode::Usage) from within clap
+    let code = match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::from(&err)
+        }
+    };
+    std::process::exit(code as i32);
 }
@@ -0,0 +1,38 @@
+use rss::{Channel, ChannelBuilder, GuidBuilder, Item, ItemBuilder};
+use rss_combine::{combine, CombineOptions};
+
+fn item(guid: &str) -> Item {
+    ItemBuilder::default()
+        .title(guid.to_string())
+        .guid(GuidBuilder::default().value(guid).build())
+        .build()
+}
+
+fn channel(guids: &[&str]) -> Channel {
+    ChannelBuilder::default()
+        .title("t")
+        .link("http://example.com/")
+        .description("d")
+        .items(guids.iter().map(|guid| item(guid)).collect::<Vec<_>>())
+        .build()
+}
+
+fn guids(channel: &Channel) -> Vec<&str> {
+    let mut guids: Vec<_> = channel.items().iter().filter_map(|item| item.guid()).map(|guid| guid.value()).collect();
+    guids.sort_unstable();
+    guids
+}
+
+#[test]
+fn entry_in_two_additional_files_is_merged_once() {
+    let extras = vec![channel(&["c", "d"]), channel(&["c", "e"])];
+    let merged = combine(channel(&["a", "b"]), extras, &CombineOptions::default());
+    assert_eq!(guids(&merged), ["a", "b", "c", "d", "e"]);
+}
+
+#[test]
+fn entry_of_main_file_is_not_merged_again() {
+    let extras = vec![channel(&["a", "c"]), channel(&["b", "c"])];
+    let merged = combine(channel(&["a", "b"]), extras, &CombineOptions::default());
+    assert_eq!(guids(&merged), ["a", "b", "c"]);
+}